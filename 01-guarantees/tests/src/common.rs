@@ -1,83 +1,2324 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Mutex, PoisonError};
 
 use assertables::{assume, assume_eq};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_pcg::Pcg64;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::ser::Serializer;
+use serde_json::{json, Value};
 use sugars::boxed;
 
+use anysystem::events::{MessageReceived, TimerFired};
+use anysystem::logger::LogEntry;
+use anysystem::process::TimerBehavior;
 use anysystem::python::PyProcessFactory;
 use anysystem::test::TestResult;
-use anysystem::{Message, System};
+use anysystem::{Context, Message, Process, ProcessEvent, ProcessState, System};
+use pyo3::types::IntoPyDict;
+use pyo3::{Python, ToPyObject};
+
+use crate::external::{ExternalProcessFactory, PythonFormatter};
+use crate::hidden::HiddenTest;
+use crate::mc_scenario::McScenario;
+use crate::overhead::OverheadMetric;
+use crate::source_check::SourceCheck;
+use crate::tests_mc::EventOrder;
 
 #[derive(Copy, Clone)]
 pub struct TestConfig<'a> {
     pub impl_path: &'a str,
+    /// Keyword arguments passed to the constructors of the solution processes.
+    pub process_kwargs: &'a HashMap<String, String>,
+    /// Rates of the local clocks of the nodes relative to the global time, 1 for the nodes not listed.
+    pub clock_rates: &'a [(&'a str, f64)],
+    pub guarantee: &'a str,
     pub sender_class: &'a str,
     pub receiver_class: &'a str,
     pub seed: u64,
     pub monkeys: u32,
+    pub chaos_jobs: u32,
+    pub chaos: ChaosParams,
+    pub max_steps: u64,
+    pub settle: Option<f64>,
+    pub mc_check_every: u32,
+    pub mc_max_violations: u32,
+    pub mc_time_limit: Option<f64>,
+    pub mc_strategy: &'a str,
+    pub mc_event_order: EventOrder,
+    pub mc_walks: u32,
+    pub mc_walk_seed: Option<u64>,
+    pub mc_min_depth: u64,
+    pub mc_max_depth: u64,
+    pub mc_por: bool,
+    pub mc_symmetry: bool,
+    pub mc_graph: Option<&'a str>,
+    pub prewarm: bool,
+    pub overhead_metric: OverheadMetric,
+    /// Number of the first delivered messages whose time is excluded from the throughput in overhead tests.
+    pub overhead_warmup: usize,
+    /// Constructs which the solutions passing the overhead tests must not or must contain.
+    pub source_check: SourceCheck<'a>,
+    pub duplicate_local_sends: bool,
     pub reliable: bool,
     pub once: bool,
     pub ordered: bool,
+    pub schedule: Option<&'a str>,
+    pub save_schedule: Option<&'a str>,
+    pub hidden_test: Option<&'a HiddenTest>,
+    pub mc_scenario: Option<&'a McScenario>,
+    pub trace_dir: Option<&'a str>,
+}
+
+/// Scenario of a chaos monkey run: the number of sent messages and the network fault rates.
+#[derive(Copy, Clone, Debug)]
+pub struct ChaosParams {
+    pub messages: usize,
+    pub drop_rate: f64,
+    pub dupl_rate: f64,
+}
+
+impl Default for ChaosParams {
+    fn default() -> Self {
+        Self {
+            messages: 50,
+            drop_rate: 0.3,
+            dupl_rate: 0.3,
+        }
+    }
+}
+
+impl ChaosParams {
+    /// Returns the scenarios smaller than this one, from the most reduced ones.
+    pub fn shrink_candidates(&self) -> Vec<Self> {
+        let mut candidates = Vec::new();
+        if self.messages > 1 {
+            candidates.push(Self {
+                messages: self.messages / 2,
+                ..*self
+            });
+            candidates.push(Self {
+                messages: self.messages - 1,
+                ..*self
+            });
+        }
+        for rate in shrink_rate(self.drop_rate) {
+            candidates.push(Self {
+                drop_rate: rate,
+                ..*self
+            });
+        }
+        for rate in shrink_rate(self.dupl_rate) {
+            candidates.push(Self {
+                dupl_rate: rate,
+                ..*self
+            });
+        }
+        candidates
+    }
+}
+
+fn shrink_rate(rate: f64) -> Vec<f64> {
+    match rate {
+        rate if rate <= 0. => vec![],
+        rate if rate < 0.05 => vec![0.],
+        rate => vec![0., rate / 2.],
+    }
+}
+
+/// Number of handler calls between the measurements of the process size with `measure_max_size`.
+pub const MAX_SIZE_FREQ: u32 = 100;
+
+/// Guards the Python interpreter state shared by all systems (loaded solution modules and the global random seed)
+/// while Python processes are created, so that systems can be built from several threads.
+static PYTHON_LOCK: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    // factories are bound to the thread, since they hold Python objects behind Rc
+    static PROCESS_FACTORIES: RefCell<HashMap<(String, String), Rc<dyn ProcessFactory>>> = RefCell::new(HashMap::new());
+}
+
+/// Creates the processes of a solution class.
+pub trait ProcessFactory {
+    /// Creates a process with the positional and keyword constructor arguments and random seed,
+    /// with `measure_max_size` the size of the process data is measured during the run (see [`System::max_size`]).
+    ///
+    /// The keyword arguments allow to tune the parameters of a solution without editing it.
+    fn build_with_kwargs(
+        &self,
+        args: &[&str],
+        kwargs: &HashMap<String, String>,
+        seed: u64,
+        measure_max_size: bool,
+    ) -> Box<dyn Process>;
+}
+
+/// Factory of the processes of a Python solution class, see [`PyProcessFactory`].
+pub struct PythonProcessFactory {
+    factory: PyProcessFactory,
+    /// Name of the solution module as it is loaded by [`PyProcessFactory::new`].
+    module: String,
+    class: String,
+}
+
+impl PythonProcessFactory {
+    pub fn new(impl_path: &str, class: &str) -> Self {
+        let module = fs::canonicalize(impl_path)
+            .unwrap()
+            .to_string_lossy()
+            .replace(".py", "");
+        Self {
+            factory: PyProcessFactory::new(impl_path, class),
+            module,
+            class: class.to_string(),
+        }
+    }
+
+    /// Runs `build` with the keyword arguments bound to the constructor of the class.
+    ///
+    /// [`PyProcessFactory`] passes only the positional arguments to the constructor, so the class `__init__`
+    /// is temporarily replaced with its partial application to the keyword arguments.
+    fn with_kwargs<R>(&self, kwargs: &HashMap<String, String>, build: impl FnOnce() -> R) -> R {
+        Python::with_gil(|py| {
+            let locals = [
+                ("module", self.module.to_object(py)),
+                ("class_name", self.class.to_object(py)),
+                ("kwargs", kwargs.to_object(py)),
+            ]
+            .into_py_dict(py);
+            py.run(
+                "import functools, sys\n\
+                 cls = getattr(sys.modules[module], class_name)\n\
+                 init = cls.__dict__.get('__init__')\n\
+                 cls.__init__ = functools.partialmethod(cls.__init__, **kwargs)",
+                None,
+                Some(locals),
+            )
+            .unwrap();
+            let result = build();
+            py.run(
+                "if init is None:\n    del cls.__init__\nelse:\n    cls.__init__ = init",
+                None,
+                Some(locals),
+            )
+            .unwrap();
+            result
+        })
+    }
+}
+
+/// Returns the seed of the Python random generator for the process, so that the randomness of the processes
+/// (e.g. backoff delays) is reproducible but not correlated between them.
+///
+/// The seed is the base seed XORed with the 64-bit FNV-1a hash of the process name (the first constructor argument),
+/// e.g. the sender and the receiver get different seeds, which are the same in every run with the same base seed.
+/// Unlike `PYTHONHASHSEED`, which is shared by the interpreter, the random generator is seeded for each process.
+fn process_seed(seed: u64, proc: &str) -> u64 {
+    let hash = proc.bytes().fold(0xcbf29ce484222325, |hash: u64, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    seed ^ hash
+}
+
+impl ProcessFactory for PythonProcessFactory {
+    fn build_with_kwargs(
+        &self,
+        args: &[&str],
+        kwargs: &HashMap<String, String>,
+        seed: u64,
+        measure_max_size: bool,
+    ) -> Box<dyn Process> {
+        let seed = process_seed(seed, args[0]);
+        let build = || match args {
+            [arg] => self.factory.build((*arg,), seed),
+            [arg1, arg2] => self.factory.build((*arg1, *arg2), seed),
+            _ => panic!("Unsupported number of process arguments: {}", args.len()),
+        };
+        let mut proc = if kwargs.is_empty() {
+            build()
+        } else {
+            self.with_kwargs(kwargs, build)
+        };
+        if measure_max_size {
+            proc.set_max_size_freq(MAX_SIZE_FREQ);
+        }
+        boxed!(proc)
+    }
+}
+
+impl ProcessFactory for ExternalProcessFactory {
+    fn build_with_kwargs(
+        &self,
+        args: &[&str],
+        kwargs: &HashMap<String, String>,
+        seed: u64,
+        measure_max_size: bool,
+    ) -> Box<dyn Process> {
+        boxed!(self.build(args, kwargs, seed, measure_max_size))
+    }
+}
+
+/// Process running on a node with a skewed clock: its local time runs at `rate` relative to the global time,
+/// e.g. a rate of 1.01 means that the clock of the node is 1% fast.
+///
+/// The process sees the local time in `ctx.time()`, and the delays of its timers are measured in the local time,
+/// so with a fast clock the timers fire earlier than the process expects. The handlers of the inner process
+/// get their own context, and its actions are replayed on the context of the node with the timer delays
+/// converted to the global time, which orders the timers of different nodes by their actual firing time.
+#[derive(Clone)]
+pub struct SkewedClockProcess {
+    proc: Box<dyn Process>,
+    proc_name: String,
+    rate: f64,
+    seed: u64,
+}
+
+impl SkewedClockProcess {
+    pub fn new(proc: Box<dyn Process>, proc_name: &str, rate: f64, seed: u64) -> Self {
+        Self {
+            proc,
+            proc_name: proc_name.to_string(),
+            rate,
+            seed,
+        }
+    }
+
+    fn handle(
+        &mut self,
+        ctx: &mut Context,
+        handler: impl FnOnce(&mut Box<dyn Process>, &mut Context) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut local_ctx = Context::basic(self.proc_name.clone(), ctx.time() * self.rate, 0., self.seed);
+        let res = handler(&mut self.proc, &mut local_ctx);
+        for action in local_ctx.actions() {
+            match action {
+                ProcessEvent::MessageSent { msg, dst, .. } => ctx.send(msg, dst),
+                ProcessEvent::LocalMessageSent { msg } => ctx.send_local(msg),
+                ProcessEvent::TimerSet { name, delay, behavior } => match behavior {
+                    TimerBehavior::SetOnce => ctx.set_timer_once(&name, delay / self.rate),
+                    TimerBehavior::OverrideExisting => ctx.set_timer(&name, delay / self.rate),
+                },
+                ProcessEvent::TimerCancelled { name } => ctx.cancel_timer(&name),
+            }
+        }
+        res
+    }
+}
+
+impl Process for SkewedClockProcess {
+    fn on_message(&mut self, msg: Message, from: String, ctx: &mut Context) -> Result<(), String> {
+        self.handle(ctx, |proc, ctx| proc.on_message(msg, from, ctx))
+    }
+
+    fn on_local_message(&mut self, msg: Message, ctx: &mut Context) -> Result<(), String> {
+        self.handle(ctx, |proc, ctx| proc.on_local_message(msg, ctx))
+    }
+
+    fn on_timer(&mut self, timer: String, ctx: &mut Context) -> Result<(), String> {
+        self.handle(ctx, |proc, ctx| proc.on_timer(timer, ctx))
+    }
+
+    fn max_size(&mut self) -> u64 {
+        self.proc.max_size()
+    }
+
+    fn state(&self) -> Result<Rc<dyn ProcessState>, String> {
+        self.proc.state()
+    }
+
+    fn set_state(&mut self, state: Rc<dyn ProcessState>) -> Result<(), String> {
+        self.proc.set_state(state)
+    }
+}
+
+/// Applies the clock rate of the node from `config.clock_rates` to the process (see [`SkewedClockProcess`]).
+fn with_clock_rate(config: &TestConfig, proc: Box<dyn Process>, proc_name: &str, node: &str) -> Box<dyn Process> {
+    match config.clock_rates.iter().find(|(n, _)| *n == node) {
+        Some(&(_, rate)) if rate != 1. => boxed!(SkewedClockProcess::new(proc, proc_name, rate, config.seed)),
+        _ => proc,
+    }
+}
+
+/// Creates the factory of the solution class according to the extension of the solution file:
+/// Java (`.java`) and Go (`.go`) solutions run in their own runtime (see [`ExternalProcessFactory`]),
+/// the other ones are loaded as Python modules.
+fn new_process_factory(impl_path: &str, class: &str) -> Rc<dyn ProcessFactory> {
+    match Path::new(impl_path).extension().and_then(|ext| ext.to_str()) {
+        Some("java") => Rc::new(ExternalProcessFactory::java(impl_path, class)),
+        Some("go") => Rc::new(ExternalProcessFactory::go(impl_path, class)),
+        _ => Rc::new(PythonProcessFactory::new(impl_path, class)),
+    }
+}
+
+/// Returns the factory of the solution class.
+///
+/// Creating a factory loads the solution module, which takes a noticeable part of a short test.
+/// With `prewarm` the module is loaded once per class and thread and the factory is reused by all later builds.
+/// Processes are still created anew after seeding the Python random generator, so the runs stay deterministic,
+/// but the module-level state of the solution is shared by all tests.
+fn process_factory(config: &TestConfig, class: &str) -> Rc<dyn ProcessFactory> {
+    if !config.prewarm {
+        return new_process_factory(config.impl_path, class);
+    }
+    PROCESS_FACTORIES.with(|factories| {
+        factories
+            .borrow_mut()
+            .entry((config.impl_path.to_string(), class.to_string()))
+            .or_insert_with(|| new_process_factory(config.impl_path, class))
+            .clone()
+    })
+}
+
+/// Returns the path of the trace file for the next system built in a test run,
+/// the files are numbered in the order the systems are built.
+fn next_trace_path(dir: &str) -> PathBuf {
+    fs::create_dir_all(dir).unwrap();
+    let count = fs::read_dir(dir).unwrap().count();
+    Path::new(dir).join(format!("system-{count:04}.jsonl"))
+}
+
+/// Returns the traces of the systems built in a test run with `trace_dir` set, one line per event,
+/// and removes the trace files, so that the directory can be reused by the next run.
+pub fn take_traces(dir: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(Vec::new());
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    paths.sort();
+    let mut traces = Vec::new();
+    for path in paths {
+        let text = fs::read_to_string(&path).map_err(|e| format!("Cannot read trace {}: {e}", path.display()))?;
+        fs::remove_file(&path).map_err(|e| format!("Cannot remove trace {}: {e}", path.display()))?;
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        traces.push((name, text.lines().map(String::from).collect()));
+    }
+    Ok(traces)
+}
+
+/// Builds the system with sender and receiver processes.
+///
+/// Nodes and processes are always started in the same order (sender first, then receiver),
+/// and each process is created right after seeding the Python random generator,
+/// so the initial state of the system depends only on the configuration (see [`dump_state`]).
+pub fn build_system(config: &TestConfig, measure_max_size: bool) -> System {
+    build_system_with(config, measure_max_size, |proc, _, _| proc)
+}
+
+/// Same as [`build_system`] but passes each process with its name and node through `wrap` before adding it.
+fn build_system_with(
+    config: &TestConfig,
+    measure_max_size: bool,
+    wrap: impl Fn(Box<dyn Process>, &str, &str) -> Box<dyn Process>,
+) -> System {
+    let _python = PYTHON_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut sys = match config.trace_dir {
+        Some(dir) => System::with_log_file(config.seed, &next_trace_path(dir)),
+        None => System::new(config.seed),
+    };
+    sys.add_node("sender-node");
+    sys.add_node("receiver-node");
+
+    let sender_f = process_factory(config, config.sender_class);
+    let sender = sender_f.build_with_kwargs(
+        &["sender", "receiver"],
+        config.process_kwargs,
+        config.seed,
+        measure_max_size,
+    );
+    let sender = with_clock_rate(config, sender, "sender", "sender-node");
+    sys.add_process("sender", wrap(sender, "sender", "sender-node"), "sender-node");
+
+    let receiver_f = process_factory(config, config.receiver_class);
+    let receiver = receiver_f.build_with_kwargs(&["receiver"], config.process_kwargs, config.seed, measure_max_size);
+    let receiver = with_clock_rate(config, receiver, "receiver", "receiver-node");
+    sys.add_process("receiver", wrap(receiver, "receiver", "receiver-node"), "receiver-node");
+
+    sys
+}
+
+/// Builds the system with sender and receiver where only the messages from receiver to sender (acknowledgements)
+/// are dropped with `ack_drop_rate`, while the messages from sender are delivered.
+///
+/// The returned link settings must be applied during the simulation, see [`LinkNetwork`].
+pub fn build_ack_loss_system(config: &TestConfig, ack_drop_rate: f64) -> (System, LinkNetwork) {
+    let mut sys = build_system(config, false);
+    let mut links = LinkNetwork::new(&mut sys, (1., 1.));
+    links.set_link_drop_rate("sender-node", "receiver-node", 0.);
+    links.set_link_drop_rate("receiver-node", "sender-node", ack_drop_rate);
+    (sys, links)
+}
+
+/// Builds the system with sender and receiver which can be split by the returned [`Partition`].
+pub fn build_partitioned_system(config: &TestConfig) -> (System, Partition) {
+    let partition = Partition::default();
+    let sys = build_system_with(config, false, |proc, proc_name, node| {
+        partition.guard(proc, proc_name, node)
+    });
+    (sys, partition)
+}
+
+/// Builds the system with sender and receiver and the link settings which can use any [`DelayDistribution`],
+/// the network delays are set to `delays` immediately (see [`LinkNetwork::new`]).
+pub fn build_link_system(config: &TestConfig, delays: (f64, f64)) -> (System, LinkNetwork) {
+    let sampled_delays = SampledDelays::default();
+    let mut sys = build_system_with(config, false, |proc, proc_name, node| {
+        sampled_delays.guard(proc, proc_name, node, config.seed)
+    });
+    let links = LinkNetwork::with_sampled_delays(&mut sys, delays, sampled_delays);
+    (sys, links)
+}
+
+/// Returns the routes of a system with several senders and receivers as `(sender, receiver)` pairs:
+/// `sender-i` sends to `receiver-j` with `j = i mod n_receivers`, counting from zero.
+pub fn multi_routes(n_senders: usize, n_receivers: usize) -> Vec<(String, String)> {
+    (0..n_senders)
+        .map(|i| (format!("sender-{i}"), format!("receiver-{}", i % n_receivers)))
+        .collect()
+}
+
+/// Builds the system with `n_senders` senders and `n_receivers` receivers routed by [`multi_routes`],
+/// so a receiver may get messages from several senders (fan-in) and the senders are spread over the receivers.
+///
+/// Each process runs on its own node named after it with the `-node` suffix. Like in [`build_system`],
+/// the senders are started first and all processes are created right after seeding the Python random generator.
+pub fn build_multi_system(config: &TestConfig, n_senders: usize, n_receivers: usize) -> System {
+    let _python = PYTHON_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut sys = match config.trace_dir {
+        Some(dir) => System::with_log_file(config.seed, &next_trace_path(dir)),
+        None => System::new(config.seed),
+    };
+    let sender_f = process_factory(config, config.sender_class);
+    for (sender, receiver) in multi_routes(n_senders, n_receivers) {
+        let node = format!("{sender}-node");
+        sys.add_node(&node);
+        let proc = sender_f.build_with_kwargs(&[&sender, &receiver], config.process_kwargs, config.seed, false);
+        sys.add_process(&sender, with_clock_rate(config, proc, &sender, &node), &node);
+    }
+    let receiver_f = process_factory(config, config.receiver_class);
+    for i in 0..n_receivers {
+        let receiver = format!("receiver-{i}");
+        let node = format!("{receiver}-node");
+        sys.add_node(&node);
+        let proc = receiver_f.build_with_kwargs(&[&receiver], config.process_kwargs, config.seed, false);
+        sys.add_process(&receiver, with_clock_rate(config, proc, &receiver, &node), &node);
+    }
+    sys
+}
+
+/// Same as [`send_messages`] for a system built with [`build_multi_system`]: sends `message_count` messages
+/// to each of the senders, taking turns between them. Returns the messages sent to each sender.
+pub fn send_multi_messages(
+    sys: &mut System,
+    routes: &[(String, String)],
+    message_count: usize,
+) -> HashMap<String, Vec<Message>> {
+    let texts = generate_message_texts(sys, message_count * routes.len());
+    let mut sent: HashMap<String, Vec<Message>> = HashMap::new();
+    for (i, text) in texts.into_iter().enumerate() {
+        let sender = &routes[i % routes.len()].0;
+        let msg = text_message(&text);
+        sys.send_local_message(sender, msg.clone());
+        sent.entry(sender.clone()).or_default().push(msg);
+        let steps = sys.gen_range(0..2);
+        for _ in 0..steps {
+            if !sys.step() {
+                break;
+            }
+        }
+    }
+    sent
+}
+
+/// Same as [`check_guarantees`] for a system built with [`build_multi_system`]: checks the messages delivered
+/// by each receiver against the messages sent to its senders.
+///
+/// The order of delivery is checked only among the messages of the same sender, since the senders are independent
+/// (see [`check_fifo_per_sender`]).
+pub fn check_multi_guarantees(
+    sys: &mut System,
+    routes: &[(String, String)],
+    sent: &HashMap<String, Vec<Message>>,
+    config: &TestConfig,
+) -> TestResult {
+    let receivers: BTreeSet<&String> = routes.iter().map(|(_, receiver)| receiver).collect();
+    for receiver in receivers {
+        let senders: Vec<&String> = routes
+            .iter()
+            .filter(|(_, r)| r == receiver)
+            .map(|(sender, _)| sender)
+            .collect();
+        let sent_to: Vec<Message> = senders.iter().flat_map(|sender| sent[*sender].clone()).collect();
+        let delivered = sys.read_local_messages(receiver);
+        let mut unordered_config = *config;
+        unordered_config.ordered = false;
+        check_delivery(&delivered, &sent_to, &unordered_config).map_err(|e| format!("{receiver}: {e}"))?;
+        if config.ordered {
+            let sent_to: HashMap<String, Vec<Message>> = senders
+                .into_iter()
+                .map(|sender| (sender.clone(), sent[sender].clone()))
+                .collect();
+            check_fifo_per_sender(&delivered, &sent_to).map_err(|e| format!("{receiver}: {e}"))?;
+        }
+    }
+    Ok(true)
+}
+
+/// Checks agreement of receivers which were sent the same messages: every receiver delivers the same set of messages.
+///
+/// Only the sets are compared, so the receivers may deliver the messages in different order or several times.
+pub fn check_agreement(receivers: &[&[Message]]) -> TestResult {
+    let Some((first, others)) = receivers.split_first() else {
+        return Ok(true);
+    };
+    let expected: BTreeSet<&str> = first.iter().map(|msg| msg.data.as_str()).collect();
+    for (i, delivered) in others.iter().enumerate() {
+        let delivered: BTreeSet<&str> = delivered.iter().map(|msg| msg.data.as_str()).collect();
+        if let Some(data) = expected.difference(&delivered).next() {
+            return Err(format!(
+                "Message {} is delivered by receiver 0 but not by receiver {}",
+                data,
+                i + 1
+            ));
+        }
+        if let Some(data) = delivered.difference(&expected).next() {
+            return Err(format!(
+                "Message {} is delivered by receiver {} but not by receiver 0",
+                data,
+                i + 1
+            ));
+        }
+    }
+    Ok(true)
+}
+
+/// Length of the generated message texts, except for the five-message phrase.
+const MESSAGE_TEXT_LEN: usize = 100;
+
+/// Generates the texts of messages sent in tests.
+///
+/// The text of the `i`-th message is its index followed by random characters, e.g. `7-Qm3x...`,
+/// 100 characters in total. So the texts are unique, and the `i`-th text depends only on the seed,
+/// not on the number of messages, which makes the runs with different message counts comparable.
+/// The only exception are five messages forming a readable phrase, which is easier to follow in the traces.
+pub fn generate_message_texts(sys: &mut System, message_count: usize) -> Vec<String> {
+    if message_count == 5 {
+        return ["distributed", "systems", "need", "some", "guarantees"]
+            .map(String::from)
+            .to_vec();
+    }
+    (0..message_count)
+        .map(|i| {
+            let prefix = format!("{i}-");
+            // the whole string is generated to keep the random generator state independent of the prefix
+            let random = sys.random_string(MESSAGE_TEXT_LEN);
+            format!("{prefix}{}", &random[prefix.len()..])
+        })
+        .collect()
+}
+
+/// Characters of the message texts in the tests of special characters: JSON syntax, escapes and non-ASCII ones.
+pub const SPECIAL_CHARACTERS: &str = "ab \"\\/{}[]:,'\n\t\u{7f}é€ёж😀";
+
+/// Generates a random string of the given length from the characters of `alphabet`.
+///
+/// [`System::random_string`] uses a fixed set of alphanumeric characters, which never need escaping in JSON.
+pub fn random_string_from(sys: &mut System, len: usize, alphabet: &str) -> String {
+    let chars: Vec<char> = alphabet.chars().collect();
+    // the index is a usize, since the type of the number is taken from the range
+    (0..len).map(|_| chars[sys.gen_range(0..chars.len())]).collect()
+}
+
+/// Creates a message with the data serialized from JSON.
+///
+/// The data is formatted as `json.dumps` in Python does, escaping the quotes, control and non-ASCII characters,
+/// e.g. `{"text": "caf\u00e9 \"1\""}`, so it matches the data of the messages produced by the solutions,
+/// and parses back to the same value. Unlike formatting the data by hand, any strings can be put into it.
+pub fn message_from_json(tip: &str, data: &Value) -> Message {
+    let mut buf = Vec::new();
+    data.serialize(&mut Serializer::with_formatter(&mut buf, PythonFormatter))
+        .expect("JSON value is always serializable");
+    Message::new(tip, &String::from_utf8(buf).unwrap())
+}
+
+/// Typed access to the fields of the message data.
+pub trait MessageFields {
+    /// Returns the field of the data parsed as `T`, or `None` if the data is not a JSON object with such a field
+    /// of this type, e.g. `msg.get::<String>("text")`.
+    fn get<T: DeserializeOwned>(&self, field: &str) -> Option<T>;
+}
+
+impl MessageFields for Message {
+    fn get<T: DeserializeOwned>(&self, field: &str) -> Option<T> {
+        let mut data: Value = serde_json::from_str(&self.data).ok()?;
+        serde_json::from_value(data.get_mut(field)?.take()).ok()
+    }
+}
+
+/// Creates a message of the user with the given text, e.g. `{"text": "distributed"}`.
+pub fn text_message(text: &str) -> Message {
+    message_from_json("MESSAGE", &json!({ "text": text }))
+}
+
+/// Generates the texts of messages which are likely to repeat, like temperature readings `25C`.
+pub fn generate_non_unique_message_texts(sys: &mut System, message_count: usize) -> Vec<String> {
+    (0..message_count)
+        // unsuffixed integer literals make an i32, a u64 would need a range like `20u64..30`
+        .map(|_| format!("{}C", sys.gen_range(20..30)))
+        .collect()
+}
+
+pub fn send_messages(sys: &mut System, message_count: usize) -> Vec<Message> {
+    send_messages_with(sys, message_count, |_| {})
+}
+
+/// Same as [`send_messages`] but with `config.duplicate_local_sends` submits each message to the sender twice in a row.
+///
+/// Such duplicates come from the application itself, so unlike network duplicates they are distinct messages:
+/// both of them are expected to be delivered by reliable guarantees, and at most once may deliver both.
+pub fn send_messages_as(sys: &mut System, config: &TestConfig, message_count: usize) -> Vec<Message> {
+    let mut texts = generate_message_texts(sys, message_count);
+    if config.duplicate_local_sends {
+        texts = texts.into_iter().flat_map(|text| [text.clone(), text]).collect();
+    }
+    send_message_texts(sys, texts)
+}
+
+/// Same as [`send_messages`] but sends messages with the given texts.
+pub fn send_message_texts(sys: &mut System, texts: Vec<String>) -> Vec<Message> {
+    send_texts_with_deps(sys, texts, |_| {}, |_| {}, |_, _| Vec::new()).0
+}
+
+/// Same as [`send_messages`] but calls `on_step` after each local message and each simulation step.
+pub fn send_messages_with(sys: &mut System, message_count: usize, on_step: impl FnMut(&mut System)) -> Vec<Message> {
+    send_messages_with_deps(sys, message_count, on_step, |_, _| Vec::new()).0
+}
+
+/// Sends the messages to the process one after another at the same simulated instant, preserving their order.
+pub fn send_local_messages(sys: &mut System, proc: &str, messages: &[Message]) {
+    for msg in messages {
+        sys.send_local_message(proc, msg.clone());
+    }
+}
+
+/// Same as [`send_messages`] but sends all messages to the sender at once without simulation steps between them.
+pub fn send_message_burst(sys: &mut System, message_count: usize) -> Vec<Message> {
+    let messages: Vec<Message> = generate_message_texts(sys, message_count)
+        .iter()
+        .map(|text| text_message(text))
+        .collect();
+    send_local_messages(sys, "sender", &messages);
+    messages
+}
+
+/// Causal predecessors of each sent message, as indices of the messages it depends on.
+pub type CausalDeps = Vec<Vec<usize>>;
+
+/// Same as [`send_messages_with`] but also stamps each message with its causal predecessors returned by `deps_of`
+/// for the message index right before the message is sent. Only already sent messages can be predecessors.
+pub fn send_messages_with_deps(
+    sys: &mut System,
+    message_count: usize,
+    on_step: impl FnMut(&mut System),
+    deps_of: impl FnMut(&mut System, usize) -> Vec<usize>,
+) -> (Vec<Message>, CausalDeps) {
+    let texts = generate_message_texts(sys, message_count);
+    send_texts_with_deps(sys, texts, |_| {}, on_step, deps_of)
+}
+
+/// Same as [`send_messages_with`] but also calls `on_before_send` right before each local message is sent
+/// to the sender, e.g. to set up the network for the messages sent by the sender in response
+/// (see [`LinkNetwork::apply_from`]).
+pub fn send_messages_with_hooks(
+    sys: &mut System,
+    message_count: usize,
+    on_before_send: impl FnMut(&mut System),
+    on_step: impl FnMut(&mut System),
+) -> Vec<Message> {
+    let texts = generate_message_texts(sys, message_count);
+    send_texts_with_deps(sys, texts, on_before_send, on_step, |_, _| Vec::new()).0
+}
+
+fn send_texts_with_deps(
+    sys: &mut System,
+    texts: Vec<String>,
+    mut on_before_send: impl FnMut(&mut System),
+    mut on_step: impl FnMut(&mut System),
+    mut deps_of: impl FnMut(&mut System, usize) -> Vec<usize>,
+) -> (Vec<Message>, CausalDeps) {
+    let message_count = texts.len();
+    let mut messages = Vec::new();
+    let mut deps = Vec::new();
+    for text in texts {
+        // a crashed sender can not accept messages
+        if sys.proc_node_is_crashed("sender") {
+            break;
+        }
+        let msg_deps = deps_of(sys, messages.len());
+        assert!(msg_deps.iter().all(|dep| *dep < messages.len()));
+        deps.push(msg_deps);
+        let msg = text_message(&text);
+        on_before_send(sys);
+        sys.send_local_message("sender", msg.clone());
+        on_step(sys);
+        // `System::gen_range` samples any `SampleUniform` type of `rand` (integers and floats)
+        // taken from the range: the number of steps is an i32 and the duration is an f64
+        if message_count <= 50 {
+            let steps = sys.gen_range(0..2);
+            for _ in 0..steps {
+                if !sys.step() {
+                    break;
+                }
+                on_step(sys);
+            }
+        } else {
+            let duration = sys.gen_range(0.0..2.0);
+            step_for_duration_with(sys, duration, &mut on_step);
+        };
+        messages.push(msg);
+    }
+    (messages, deps)
+}
+
+/// Returns the time of the next pending event if any.
+pub fn next_event_time(sys: &System) -> Option<f64> {
+    sys.sim().dump_events().first().map(|e| e.time)
+}
+
+/// Same as `System::step_for_duration` but calls `on_step` after each simulation step.
+pub fn step_for_duration_with(sys: &mut System, duration: f64, mut on_step: impl FnMut(&mut System)) -> bool {
+    let end_time = sys.time() + duration;
+    while let Some(time) = next_event_time(sys) {
+        if time > end_time {
+            break;
+        }
+        sys.step();
+        on_step(sys);
+    }
+    // no events left before end_time, so this only advances the clock
+    sys.step_for_duration(end_time - sys.time())
+}
+
+/// Same as `System::step_for_duration` but calls `on_event` for each event logged during the window
+/// in the order the events fired, e.g. to inject a fault right after a particular delivery.
+pub fn step_for_duration_with_events(
+    sys: &mut System,
+    duration: f64,
+    mut on_event: impl FnMut(&mut System, &LogEntry),
+) -> bool {
+    let mut next_entry = sys.logger().trace().len();
+    step_for_duration_with(sys, duration, |sys| {
+        let entries: Vec<LogEntry> = sys.logger().trace()[next_entry..].to_vec();
+        next_entry += entries.len();
+        for entry in &entries {
+            on_event(sys, entry);
+        }
+    })
+}
+
+/// Returns the messages currently in flight over the network as (source, destination, message, delivery time)
+/// ordered by their delivery time. Each duplicate of a message is returned separately.
+pub fn in_flight_messages(sys: &System) -> Vec<(String, String, Message, f64)> {
+    sys.sim()
+        .dump_events()
+        .into_iter()
+        .filter_map(|event| {
+            event
+                .data
+                .downcast_ref::<MessageReceived>()
+                .map(|e| (e.src.clone(), e.dst.clone(), e.msg.clone(), event.time))
+        })
+        .collect()
+}
+
+/// Advances the simulation by `duration` units of time and fails if the sender sends any network message meanwhile,
+/// e.g. because it keeps retransmitting the messages which were already acknowledged.
+///
+/// The sent messages are detected by inspecting the messages in flight after each step, so the messages
+/// which were in flight before the call are not counted.
+pub fn assert_sender_idle_after(sys: &mut System, duration: f64) -> TestResult {
+    let in_flight = |sys: &System| -> Vec<(Message, f64)> {
+        in_flight_messages(sys)
+            .into_iter()
+            .filter(|(src, ..)| src == "sender")
+            .map(|(_, _, msg, time)| (msg, time))
+            .collect()
+    };
+    let before = in_flight(sys);
+    let mut sent = None;
+    step_for_duration_with(sys, duration, |sys| {
+        if sent.is_none() {
+            sent = in_flight(sys)
+                .into_iter()
+                .find(|(msg, time)| {
+                    !before
+                        .iter()
+                        .any(|(m, t)| m.tip == msg.tip && m.data == msg.data && t == time)
+                })
+                .map(|(msg, _)| (sys.time(), msg));
+        }
+    });
+    match sent {
+        Some((time, msg)) => Err(format!(
+            "Sender is not idle: sent {} {} at time {:.3}",
+            msg.tip, msg.data, time
+        )),
+        None => Ok(true),
+    }
+}
+
+/// Steps through the simulation until the receiver delivers `count` messages and the messages it has sent
+/// to the sender (e.g. acknowledgements) arrive, so that the sender has no reason to send anything more.
+///
+/// Fails if this does not happen within `max_steps` events.
+pub fn step_until_acknowledged(sys: &mut System, count: usize, max_steps: u64) -> TestResult {
+    let acknowledged = |sys: &System| {
+        pending_delivered_count(sys) >= count && !in_flight_messages(sys).iter().any(|(src, ..)| src == "receiver")
+    };
+    let mut steps = 0;
+    while !acknowledged(sys) {
+        assume!(
+            steps < max_steps && sys.step(),
+            format!(
+                "Receiver did not deliver {} messages and acknowledge them in {} steps",
+                count, steps
+            )
+        )?;
+        steps += 1;
+    }
+    Ok(true)
+}
+
+/// Same as `System::step_until_no_events` but stops after processing `max_steps` events.
+/// Returns whether the system quiesced, i.e. there are no events left.
+pub fn step_until_no_events_or(sys: &mut System, max_steps: u64) -> bool {
+    step_until_no_events_or_with(sys, max_steps, |_| {})
+}
+
+/// Same as [`step_until_no_events_or`] but calls `on_step` after each simulation step.
+pub fn step_until_no_events_or_with(sys: &mut System, max_steps: u64, mut on_step: impl FnMut(&mut System)) -> bool {
+    for _ in 0..max_steps {
+        if !sys.step() {
+            return true;
+        }
+        on_step(sys);
+    }
+    next_event_time(sys).is_none()
+}
+
+/// Same as `System::step_until_no_events` but fails after processing `max_steps` events,
+/// so that a solution generating events endlessly does not hang the tests.
+pub fn step_until_no_events(sys: &mut System, max_steps: u64) -> TestResult {
+    assume!(step_until_no_events_or(sys, max_steps), not_quiesced_message(max_steps))
+}
+
+/// Same as [`step_until_no_events`] but calls `on_step` after each simulation step.
+pub fn step_until_no_events_with(sys: &mut System, max_steps: u64, on_step: impl FnMut(&mut System)) -> TestResult {
+    assume!(
+        step_until_no_events_or_with(sys, max_steps, on_step),
+        not_quiesced_message(max_steps)
+    )
+}
+
+fn not_quiesced_message(max_steps: u64) -> String {
+    format!(
+        "Simulation did not quiesce after {} steps, possible infinite loop (see --max-steps)",
+        max_steps
+    )
+}
+
+/// Steps through the simulation until the receiver delivers no new messages for `settle` time,
+/// stopping even if there are pending events.
+///
+/// This termination condition is meant for solutions with persistent background timers (e.g. heartbeats),
+/// for which [`step_until_no_events`] never returns. Otherwise [`step_until_no_events`] should be preferred,
+/// since it also ensures that the solution stops sending messages once everything is delivered.
+pub fn step_for_duration_after_last_delivery(sys: &mut System, settle: f64, max_steps: u64) -> TestResult {
+    let mut delivered_count = pending_delivered_count(sys);
+    let mut last_delivery_time = sys.time();
+    let mut steps = 0;
+    while let Some(time) = next_event_time(sys) {
+        if time > last_delivery_time + settle {
+            break;
+        }
+        sys.step();
+        steps += 1;
+        let count = pending_delivered_count(sys);
+        if count != delivered_count {
+            delivered_count = count;
+            last_delivery_time = sys.time();
+        }
+        assume!(
+            steps < max_steps,
+            format!(
+                "Deliveries are not settled after {} steps, possible infinite loop (see --max-steps)",
+                steps
+            )
+        )?;
+    }
+    Ok(true)
+}
+
+/// Finishes the test run using the termination condition selected in the config.
+pub fn step_until_settled(sys: &mut System, config: &TestConfig) -> TestResult {
+    match config.settle {
+        Some(settle) => step_for_duration_after_last_delivery(sys, settle, config.max_steps),
+        None => step_until_no_events(sys, config.max_steps),
+    }
+}
+
+/// Returns a textual dump of the system state: current time, state of each process and pending events.
+///
+/// Processes are listed by name and events by their order of occurrence,
+/// so equal systems produce identical dumps regardless of the internal storage order.
+pub fn dump_state(sys: &System) -> String {
+    let mut dump = format!("time: {}\n", sys.time());
+    let mut proc_names = sys.process_names();
+    proc_names.sort();
+    for proc in proc_names {
+        let node_name = sys.proc_node_name(&proc);
+        let node = sys.get_node(&node_name).unwrap();
+        let state = node.get_process(&proc).unwrap().state().unwrap();
+        let state = state.downcast_rc::<String>().unwrap();
+        dump.push_str(&format!("process {proc} on {node_name}: {state}\n"));
+    }
+    for event in sys.sim().dump_events() {
+        dump.push_str(&format!(
+            "event {} at {}: {} -> {}",
+            event.id, event.time, event.src, event.dst
+        ));
+        if let Some(e) = event.data.downcast_ref::<MessageReceived>() {
+            dump.push_str(&format!(
+                " message {} from {} to {}: {}",
+                e.msg.tip, e.src, e.dst, e.msg.data
+            ));
+        }
+        dump.push('\n');
+    }
+    dump
+}
+
+/// Callback receiving the name of a process and a message sent or delivered by it.
+pub type MessageHook<'a> = Box<dyn FnMut(&str, &Message) + 'a>;
+
+/// Observers notified about each network message sent by a process
+/// and each message delivered by a process to its local user.
+///
+/// Hooks are fired in the order of events from the system trace, so [`MessageHooks::fire`] should be called
+/// after each step (see [`send_messages_with`] and [`step_until_no_events_with`]).
+#[derive(Default)]
+pub struct MessageHooks<'a> {
+    on_send: Vec<MessageHook<'a>>,
+    on_deliver: Vec<MessageHook<'a>>,
+    next_entry: usize,
+}
+
+impl<'a> MessageHooks<'a> {
+    pub fn on_send(&mut self, hook: impl FnMut(&str, &Message) + 'a) {
+        self.on_send.push(Box::new(hook));
+    }
+
+    pub fn on_deliver(&mut self, hook: impl FnMut(&str, &Message) + 'a) {
+        self.on_deliver.push(Box::new(hook));
+    }
+
+    /// Fires the hooks for the events occurred since the previous call.
+    pub fn fire(&mut self, sys: &System) {
+        let logger = sys.logger();
+        let trace = logger.trace();
+        for entry in &trace[self.next_entry..] {
+            match entry {
+                LogEntry::MessageSent { src_proc, msg, .. } => {
+                    self.on_send.iter_mut().for_each(|hook| hook(src_proc, msg));
+                }
+                LogEntry::LocalMessageSent { proc, msg, .. } => {
+                    self.on_deliver.iter_mut().for_each(|hook| hook(proc, msg));
+                }
+                _ => {}
+            }
+        }
+        self.next_entry = trace.len();
+    }
+}
+
+/// Returns the number of messages delivered by the receiver and not yet read by the test.
+pub fn pending_delivered_count(sys: &System) -> usize {
+    sys.get_node("receiver-node").unwrap().local_outbox("receiver").len()
+}
+
+/// Field of the process state (the JSON object returned by `get_state`) holding the durable storage of the process.
+pub const STORAGE_FIELD: &str = "storage";
+
+/// Returns the durable storage of the process, i.e. the [`STORAGE_FIELD`] of its state, if the process has it.
+fn process_storage(sys: &System, node: &str, proc: &str) -> Option<Value> {
+    let state = sys.get_node(node)?.get_process(proc)?.state().ok()?;
+    let state = state.downcast_rc::<String>().ok()?;
+    let mut state: Value = serde_json::from_str(&state).ok()?;
+    state.get_mut(STORAGE_FIELD).map(Value::take)
+}
+
+/// Crashes the receiver node and starts a new receiver process on it with the storage persisted before the crash.
+///
+/// Only the [`STORAGE_FIELD`] of the receiver state is treated as its durable storage: the new process is created
+/// by the constructor and then gets the persisted storage, while all other data of the process, its timers
+/// and the messages in flight to it are lost. Returns whether the receiver had the storage to persist.
+/// The messages delivered before the crash should be read beforehand, since the process outbox is cleared.
+pub fn restart_receiver(sys: &mut System, config: &TestConfig) -> bool {
+    let storage = process_storage(sys, "receiver-node", "receiver");
+    sys.crash_node("receiver-node");
+    sys.recover_node("receiver-node");
+    let receiver = {
+        let _python = PYTHON_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let receiver_f = process_factory(config, config.receiver_class);
+        let receiver = receiver_f.build_with_kwargs(&["receiver"], config.process_kwargs, config.seed, false);
+        with_clock_rate(config, receiver, "receiver", "receiver-node")
+    };
+    let Some(storage) = storage else {
+        sys.add_process("receiver", receiver, "receiver-node");
+        return false;
+    };
+    let state = receiver.state().unwrap();
+    let mut state: Value = serde_json::from_str(&state.downcast_rc::<String>().unwrap()).unwrap();
+    state[STORAGE_FIELD] = storage;
+    sys.add_process("receiver", receiver, "receiver-node");
+    sys.get_mut_node("receiver-node")
+        .unwrap()
+        .set_process_state("receiver", Rc::new(state.to_string()));
+    true
+}
+
+/// Returns the messages sent by the process to other nodes so far, including the dropped ones.
+pub fn network_messages_sent_by(sys: &System, proc: &str) -> Vec<Message> {
+    sys.logger()
+        .trace()
+        .iter()
+        .filter_map(|entry| match entry {
+            LogEntry::MessageSent {
+                src_proc,
+                src_node,
+                dst_node,
+                msg,
+                ..
+            } if src_proc == proc && src_node != dst_node => Some(msg.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the number of network messages sent by the process, the part of
+/// [`Network::network_message_count`](anysystem::Network::network_message_count) attributed to it.
+///
+/// Like the global counters, the per-process ones are collected from the trace of the system and start from zero
+/// in each new system.
+pub fn message_count_from(sys: &System, proc: &str) -> u64 {
+    network_messages_sent_by(sys, proc).len() as u64
+}
+
+/// Returns the size of network messages sent by the process, the part of
+/// [`Network::traffic`](anysystem::Network::traffic) attributed to it.
+pub fn traffic_from(sys: &System, proc: &str) -> u64 {
+    network_messages_sent_by(sys, proc)
+        .iter()
+        .map(|msg| msg.size() as u64)
+        .sum()
+}
+
+/// Returns the ordered message events of the simulation run recorded by the system logger, one line per event:
+/// network messages sent, dropped and received (a repeated id means a duplicate) and local messages produced.
+///
+/// Unlike the `--debug` output it is captured per system, so a test can attach it to its error (see [`with_event_log`]).
+pub fn event_log(sys: &System) -> Vec<String> {
+    sys.logger()
+        .trace()
+        .iter()
+        .filter_map(|entry| match entry {
+            LogEntry::MessageSent {
+                time,
+                msg_id,
+                src_proc,
+                dst_proc,
+                msg,
+                ..
+            } => Some(format!(
+                "{time:.3} sent {msg_id} {src_proc} -> {dst_proc}: {} {}",
+                msg.tip, msg.data
+            )),
+            LogEntry::MessageDropped {
+                time,
+                msg_id,
+                src_proc,
+                dst_proc,
+                msg,
+                ..
+            } => Some(format!(
+                "{time:.3} dropped {msg_id} {src_proc} -> {dst_proc}: {} {}",
+                msg.tip, msg.data
+            )),
+            LogEntry::MessageReceived {
+                time,
+                msg_id,
+                src_proc,
+                dst_proc,
+                msg,
+                ..
+            } => Some(format!(
+                "{time:.3} received {msg_id} {src_proc} -> {dst_proc}: {} {}",
+                msg.tip, msg.data
+            )),
+            LogEntry::LocalMessageSent { time, proc, msg, .. } => {
+                Some(format!("{time:.3} local {proc}: {} {}", msg.tip, msg.data))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Appends the [`event_log`] of the system to the error of a failed check.
+pub fn with_event_log(sys: &System, err: String) -> String {
+    format!("{err}\nEvents:\n{}", event_log(sys).join("\n"))
+}
+
+/// Returns the number of messages dropped by the network so far.
+pub fn dropped_message_count(sys: &System) -> u64 {
+    sys.logger()
+        .trace()
+        .iter()
+        .filter(|entry| matches!(entry, LogEntry::MessageDropped { .. }))
+        .count() as u64
+}
+
+/// Returns the number of extra copies of network messages delivered so far due to duplication.
+pub fn duplicated_message_count(sys: &System) -> u64 {
+    let mut delivered_ids = HashSet::new();
+    sys.logger()
+        .trace()
+        .iter()
+        .filter(|entry| match entry {
+            LogEntry::MessageReceived {
+                msg_id,
+                src_node,
+                dst_node,
+                ..
+            } => src_node != dst_node && !delivered_ids.insert(msg_id.clone()),
+            _ => false,
+        })
+        .count() as u64
+}
+
+/// Deterministic message loss: the network drops messages with its configured drop rate
+/// until `budget` messages are dropped, then delivers everything.
+///
+/// The drops are counted from the system trace between simulation steps, so [`DropBudget::enforce`] should be called
+/// after each local message and step (see [`send_messages_with`] and [`step_until_no_events_with`]).
+/// If a single event makes a process send several messages, all of them are subject to the drop rate,
+/// so the step spending the budget may overdraw it, which is reported by [`DropBudget::dropped_within_budget`].
+///
+/// Duplication is independent of the budget: a dropped message is never duplicated,
+/// while a delivered one can still be duplicated according to the duplication rate.
+/// Only dropped messages count towards the budget, duplicates do not.
+pub struct DropBudget {
+    budget: u64,
+    /// Length of the system trace when the budget was spent.
+    spent_at: Option<usize>,
+}
+
+impl DropBudget {
+    pub fn new(budget: u64) -> Self {
+        Self { budget, spent_at: None }
+    }
+
+    /// Disables message drops once the budget is spent, until then the configured drop rate applies.
+    pub fn enforce(&mut self, sys: &mut System) {
+        if self.spent_at.is_none() && dropped_message_count(sys) >= self.budget {
+            sys.network().set_drop_rate(0.);
+            self.spent_at = Some(sys.logger().trace().len());
+        }
+    }
+
+    /// Checks that no message is dropped after the budget is spent and returns the number of dropped messages,
+    /// which is at most the budget unless the last step within the budget dropped several messages at once.
+    pub fn dropped_within_budget(&self, sys: &System) -> Result<u64, String> {
+        let spent_at = self.spent_at.unwrap_or(usize::MAX);
+        let late_drops = sys
+            .logger()
+            .trace()
+            .iter()
+            .skip(spent_at)
+            .filter(|entry| matches!(entry, LogEntry::MessageDropped { .. }))
+            .count();
+        assume_eq!(
+            late_drops,
+            0,
+            format!(
+                "Network dropped {} messages after the budget of {} was spent",
+                late_drops, self.budget
+            )
+        )?;
+        Ok(dropped_message_count(sys))
+    }
+}
+
+/// Deterministic loss of the network messages matching a predicate, e.g. all messages carrying a certain text.
+/// Only the first `limit` matching messages are dropped, so that reliable guarantees can still be provided.
+///
+/// The network decides whether to drop a message when it is sent, before its content can be inspected,
+/// so the matching messages are found by rerunning the test (see [`DropMatching::update`]).
+/// Each run drops the matching messages found so far by their index as in [`DropNth`].
+/// Since the runs are deterministic, a run differs from the previous one only after the newly found message.
+pub struct DropMatching<P: Fn(&Message) -> bool> {
+    predicate: P,
+    limit: usize,
+    indices: BTreeSet<u64>,
+}
+
+impl<P: Fn(&Message) -> bool> DropMatching<P> {
+    pub fn new(predicate: P, limit: usize) -> Self {
+        Self {
+            predicate,
+            limit,
+            indices: BTreeSet::new(),
+        }
+    }
+
+    /// Sets the drop rate for the next network message.
+    pub fn apply(&self, sys: &mut System) {
+        let next = sys.network().network_message_count();
+        sys.network()
+            .set_drop_rate(if self.indices.contains(&next) { 1. } else { 0. });
+    }
+
+    /// Looks for the first matching message within the limit which was not dropped in the finished run.
+    /// Returns whether such message is found and the test should be rerun to drop it.
+    pub fn update(&mut self, sys: &System) -> Result<bool, String> {
+        let mut matching = Vec::new();
+        let mut dropped = HashSet::new();
+        let mut index = 0;
+        for entry in sys.logger().trace() {
+            match entry {
+                LogEntry::MessageSent {
+                    msg_id,
+                    src_node,
+                    dst_node,
+                    msg,
+                    ..
+                } if src_node != dst_node => {
+                    if (self.predicate)(msg) {
+                        matching.push((index, msg_id.clone()));
+                    }
+                    index += 1;
+                }
+                LogEntry::MessageDropped { msg_id, .. } => {
+                    dropped.insert(msg_id.clone());
+                }
+                _ => {}
+            }
+        }
+        let missed = matching
+            .into_iter()
+            .take(self.limit)
+            .find(|(_, msg_id)| !dropped.contains(msg_id));
+        let Some((index, _)) = missed else {
+            return Ok(false);
+        };
+        if !self.indices.insert(index) {
+            return Err(format!(
+                "Network message {index} matches the predicate but can not be dropped separately from the messages sent along with it"
+            ));
+        }
+        // the run after this message is going to change, so later messages may not match anymore
+        self.indices.retain(|i| *i <= index);
+        Ok(true)
+    }
+
+    /// Returns the number of messages dropped due to the predicate.
+    pub fn dropped_count(&self) -> usize {
+        self.indices.len()
+    }
+}
+
+/// Application consuming the messages delivered by a process in bursts, with a bounded buffer
+/// of delivered but not yet consumed messages.
+///
+/// While the buffer is full, the network delays all messages sent at that time until the next consumption,
+/// so the process gets no new messages until the application consumes the buffered ones, but no message is lost.
+/// This models a stalled application applying backpressure up the stack. The messages already in flight are still
+/// delivered, so the buffer can slightly exceed its capacity. By default (without a consumer) the buffer is unlimited.
+///
+/// The buffer is checked between simulation steps, so [`SlowConsumer::apply`] should be called
+/// after each local message and step (see [`send_messages_with`] and [`step_until_no_events_with`]).
+pub struct SlowConsumer {
+    proc: String,
+    capacity: usize,
+    read_period: f64,
+    next_read_time: f64,
+    delays: (f64, f64),
+    consumed: Vec<Message>,
+}
+
+impl SlowConsumer {
+    /// Creates a consumer reading all buffered messages of `proc` every `read_period` time
+    /// and sets the network delays used while the buffer is not full.
+    pub fn new(sys: &mut System, proc: &str, capacity: usize, read_period: f64, delays: (f64, f64)) -> Self {
+        sys.network().set_delays(delays.0, delays.1);
+        Self {
+            proc: proc.to_string(),
+            capacity,
+            read_period,
+            next_read_time: sys.time() + read_period,
+            delays,
+            consumed: Vec::new(),
+        }
+    }
+
+    /// Consumes the buffered messages if it is time to, and holds or releases the delivery to the process.
+    pub fn apply(&mut self, sys: &mut System) {
+        if sys.time() >= self.next_read_time {
+            self.consumed.extend(sys.read_local_messages(&self.proc));
+            self.next_read_time = sys.time() + self.read_period;
+        }
+        let (min_delay, max_delay) = self.delays;
+        if sys.local_outbox(&self.proc).len() >= self.capacity {
+            let hold = self.next_read_time - sys.time();
+            sys.network().set_delays(min_delay + hold, max_delay + hold);
+        } else {
+            sys.network().set_delays(min_delay, max_delay);
+        }
+    }
+
+    /// Consumes the rest of the buffered messages and returns all consumed messages in the order of delivery.
+    pub fn finish(mut self, sys: &mut System) -> Vec<Message> {
+        self.consumed.extend(sys.read_local_messages(&self.proc));
+        sys.network().set_delays(self.delays.0, self.delays.1);
+        self.consumed
+    }
+}
+
+/// Deterministic loss of a single network message: the `n`-th message sent between different nodes
+/// (counting from zero) is dropped, and all other messages are delivered.
+///
+/// The drop rate is switched between simulation steps, so [`DropNth::apply`] should be called
+/// after each local message and step (see [`send_messages_with`] and [`step_until_no_events_with`]).
+/// If a single event makes a process send several messages, all of them share the fate of the first one.
+pub struct DropNth {
+    n: u64,
+}
+
+impl DropNth {
+    pub fn new(sys: &mut System, n: u64) -> Self {
+        let drop = Self { n };
+        drop.apply(sys);
+        drop
+    }
+
+    /// Sets the drop rate for the next network message.
+    pub fn apply(&self, sys: &mut System) {
+        let next = sys.network().network_message_count();
+        sys.network().set_drop_rate(if next == self.n { 1. } else { 0. });
+    }
+}
+
+/// Deterministic duplication of a single network message: the `n`-th message sent between different nodes
+/// (counting from zero) is duplicated, and all other messages are delivered once.
+///
+/// The network decides how many copies of the message to deliver, and each copy gets its own sampled delay.
+/// Like [`DropNth`], the duplication rate is switched between simulation steps, so [`DuplicateNth::apply`]
+/// should be called after each local message and step.
+pub struct DuplicateNth {
+    n: u64,
+}
+
+impl DuplicateNth {
+    pub fn new(sys: &mut System, n: u64) -> Self {
+        let duplicate = Self { n };
+        duplicate.apply(sys);
+        duplicate
+    }
+
+    /// Sets the duplication rate for the next network message.
+    pub fn apply(&self, sys: &mut System) {
+        let next = sys.network().network_message_count();
+        sys.network().set_dupl_rate(if next == self.n { 1. } else { 0. });
+    }
+}
+
+/// Memory budget of a process: once the size of the process (as reported by `System::max_size`)
+/// exceeds the limit, its node is crashed as if the process was killed by the OOM killer.
+///
+/// The size is measured between simulation steps, so [`MemoryLimit::enforce`] should be called after each step
+/// (see [`send_messages_with`] and [`step_until_no_events_with`]). The system should be built with
+/// `measure_max_size` enabled.
+pub struct MemoryLimit {
+    proc: String,
+    limit: u64,
+    oom: Option<(f64, u64)>,
+}
+
+impl MemoryLimit {
+    pub fn new(proc: &str, limit: u64) -> Self {
+        Self {
+            proc: proc.to_string(),
+            limit,
+            oom: None,
+        }
+    }
+
+    /// Kills the process if it exceeds the limit.
+    pub fn enforce(&mut self, sys: &mut System) {
+        if self.oom.is_some() {
+            return;
+        }
+        let size = sys.max_size(&self.proc);
+        if size > self.limit {
+            sys.crash_node(&sys.proc_node_name(&self.proc));
+            self.oom = Some((sys.time(), size));
+        }
+    }
+
+    /// Fails if the process was killed.
+    pub fn check(&self) -> TestResult {
+        match self.oom {
+            Some((time, size)) => Err(format!(
+                "Process {} is killed by OOM at time {:.3} (memory {} > limit {})",
+                self.proc, time, size, self.limit
+            )),
+            None => Ok(true),
+        }
+    }
+}
+
+/// Time series of the size of a process (as reported by `System::max_size`) sampled during the run.
+///
+/// A sample is taken every [`MAX_SIZE_FREQ`] simulation steps, matching the cadence of the size measurements,
+/// so [`SizeHistory::record`] should be called after each step (see [`send_messages_with`]
+/// and [`step_until_no_events_with`]). The reported size is the peak so far, so the history shows
+/// whether the memory keeps growing during the run or levels off.
+pub struct SizeHistory {
+    proc: String,
+    steps: u32,
+    samples: Vec<(f64, u64)>,
+}
+
+impl SizeHistory {
+    pub fn new(proc: &str) -> Self {
+        Self {
+            proc: proc.to_string(),
+            steps: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Samples the size of the process every [`MAX_SIZE_FREQ`] calls.
+    pub fn record(&mut self, sys: &mut System) {
+        self.steps += 1;
+        if self.steps % MAX_SIZE_FREQ == 0 {
+            self.samples.push((sys.time(), sys.max_size(&self.proc)));
+        }
+    }
+
+    /// Returns the `(time, size)` samples.
+    pub fn samples(&self) -> &[(f64, u64)] {
+        &self.samples
+    }
+}
+
+/// Transient congestion event: network delays are multiplied by `multiplier`
+/// for `duration` starting at `at_time`.
+///
+/// The spike window is fixed in simulation time and does not depend on the random seed.
+/// Delays are switched between simulation steps, so [`LatencySpike::apply`] should be called
+/// after each step (see [`send_messages_with`] and [`step_until_no_events_with`]).
+pub struct LatencySpike {
+    at_time: f64,
+    duration: f64,
+    multiplier: f64,
+    min_delay: f64,
+    max_delay: f64,
+}
+
+impl LatencySpike {
+    /// Creates a spike over the base network delays, which are set immediately.
+    pub fn schedule(sys: &mut System, at_time: f64, duration: f64, multiplier: f64, base_delays: (f64, f64)) -> Self {
+        let (min_delay, max_delay) = base_delays;
+        sys.network().set_delays(min_delay, max_delay);
+        Self {
+            at_time,
+            duration,
+            multiplier,
+            min_delay,
+            max_delay,
+        }
+    }
+
+    /// Returns the time when the spike is over and the messages sent during it are delivered.
+    pub fn passed_time(&self) -> f64 {
+        self.at_time + self.duration + self.max_delay * self.multiplier
+    }
+
+    /// Sets the network delays according to the time of the next event.
+    pub fn apply(&self, sys: &mut System) {
+        let time = next_event_time(sys).unwrap_or(sys.time());
+        if time >= self.at_time && time < self.at_time + self.duration {
+            sys.network()
+                .set_delays(self.min_delay * self.multiplier, self.max_delay * self.multiplier);
+        } else {
+            sys.network().set_delays(self.min_delay, self.max_delay);
+        }
+    }
+}
+
+/// Network partition: the nodes are split into isolated groups with [`Partition::split`]
+/// until [`Partition::heal`], messages between different groups meanwhile are dropped.
+///
+/// The network decides whether a message is lost when it is sent, so the messages sent between the groups
+/// are dropped by disabling the links between them. The messages already in flight when the partition forms
+/// are dropped on delivery if they cross the boundary, which is done by the processes guarded by the partition
+/// (see [`build_partitioned_system`]): they ignore such messages, which are not reported as dropped by the network.
+#[derive(Clone, Default)]
+pub struct Partition {
+    state: Rc<RefCell<PartitionState>>,
+}
+
+#[derive(Default)]
+struct PartitionState {
+    groups: Vec<Vec<String>>,
+    /// Nodes of the guarded processes.
+    proc_nodes: HashMap<String, String>,
+}
+
+impl Partition {
+    /// Splits the network into isolated groups of nodes, replacing the previous split if any.
+    pub fn split(&self, sys: &mut System, groups: &[&[&str]]) {
+        self.heal(sys);
+        let mut net = sys.network();
+        for (i, group1) in groups.iter().enumerate() {
+            for group2 in &groups[i + 1..] {
+                net.make_partition(group1, group2);
+            }
+        }
+        self.state.borrow_mut().groups = groups
+            .iter()
+            .map(|group| group.iter().map(|node| node.to_string()).collect())
+            .collect();
+    }
+
+    /// Heals the network, so that all nodes can communicate again.
+    pub fn heal(&self, sys: &mut System) {
+        let groups = std::mem::take(&mut self.state.borrow_mut().groups);
+        let mut net = sys.network();
+        for (i, group1) in groups.iter().enumerate() {
+            for group2 in &groups[i + 1..] {
+                for node1 in group1 {
+                    for node2 in group2 {
+                        net.enable_link(node1, node2);
+                        net.enable_link(node2, node1);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn is_split(&self) -> bool {
+        !self.state.borrow().groups.is_empty()
+    }
+
+    /// Returns whether a message from the process to the node crosses the boundary between the groups.
+    fn crosses(&self, from_proc: &str, to_node: &str) -> bool {
+        let state = self.state.borrow();
+        let Some(from_node) = state.proc_nodes.get(from_proc) else {
+            return false;
+        };
+        let group_of = |node: &str| state.groups.iter().position(|group| group.iter().any(|n| n == node));
+        group_of(from_node) != group_of(to_node)
+    }
+
+    /// Wraps the process on the node, so that it ignores the messages crossing the partition when they are delivered.
+    pub fn guard(&self, proc: Box<dyn Process>, proc_name: &str, node: &str) -> Box<dyn Process> {
+        self.state
+            .borrow_mut()
+            .proc_nodes
+            .insert(proc_name.to_string(), node.to_string());
+        boxed!(PartitionedProcess {
+            proc,
+            node: node.to_string(),
+            partition: self.clone(),
+        })
+    }
+
+    /// Splits the network into the groups for `duration` starting at `at_time`.
+    pub fn schedule(&self, groups: &[&[&str]], at_time: f64, duration: f64) -> PartitionSchedule {
+        PartitionSchedule {
+            partition: self.clone(),
+            groups: groups
+                .iter()
+                .map(|group| group.iter().map(|node| node.to_string()).collect())
+                .collect(),
+            at_time,
+            duration,
+        }
+    }
+}
+
+/// Process guarded by a [`Partition`].
+#[derive(Clone)]
+struct PartitionedProcess {
+    proc: Box<dyn Process>,
+    node: String,
+    partition: Partition,
+}
+
+impl Process for PartitionedProcess {
+    fn on_message(&mut self, msg: Message, from: String, ctx: &mut Context) -> Result<(), String> {
+        if self.partition.is_split() && self.partition.crosses(&from, &self.node) {
+            return Ok(());
+        }
+        self.proc.on_message(msg, from, ctx)
+    }
+
+    fn on_local_message(&mut self, msg: Message, ctx: &mut Context) -> Result<(), String> {
+        self.proc.on_local_message(msg, ctx)
+    }
+
+    fn on_timer(&mut self, timer: String, ctx: &mut Context) -> Result<(), String> {
+        self.proc.on_timer(timer, ctx)
+    }
+
+    fn max_size(&mut self) -> u64 {
+        self.proc.max_size()
+    }
+
+    fn state(&self) -> Result<Rc<dyn ProcessState>, String> {
+        self.proc.state()
+    }
+
+    fn set_state(&mut self, state: Rc<dyn ProcessState>) -> Result<(), String> {
+        self.proc.set_state(state)
+    }
+}
+
+/// Partition of the network for a time window, see [`Partition::schedule`].
+///
+/// The network is split and healed between simulation steps, so [`PartitionSchedule::apply`] should be called
+/// after each step (see [`send_messages_with`] and [`step_until_no_events_with`]).
+pub struct PartitionSchedule {
+    partition: Partition,
+    groups: Vec<Vec<String>>,
+    at_time: f64,
+    duration: f64,
+}
+
+impl PartitionSchedule {
+    /// Returns the time when the partition is healed.
+    pub fn healed_time(&self) -> f64 {
+        self.at_time + self.duration
+    }
+
+    /// Splits or heals the network according to the time of the next event.
+    pub fn apply(&self, sys: &mut System) {
+        let time = next_event_time(sys).unwrap_or(sys.time());
+        let active = time >= self.at_time && time < self.healed_time();
+        if active == self.partition.is_split() {
+            return;
+        }
+        if active {
+            let groups: Vec<Vec<&str>> = self
+                .groups
+                .iter()
+                .map(|group| group.iter().map(String::as_str).collect())
+                .collect();
+            let groups: Vec<&[&str]> = groups.iter().map(Vec::as_slice).collect();
+            self.partition.split(sys, &groups);
+        } else {
+            self.partition.heal(sys);
+        }
+    }
+}
+
+/// Network drop rate varying over time, e.g. an outage window that heals.
+///
+/// The schedule is a list of `(time, drop_rate)` points, between them the most recent rate holds
+/// and before the first one the rate is zero. Like delays in [`LatencySpike`], the rate is switched
+/// between simulation steps, so [`DropRateSchedule::apply`] should be called after each step
+/// (see [`send_messages_with`] and [`step_until_no_events_with`]).
+pub struct DropRateSchedule {
+    points: Vec<(f64, f64)>,
+}
+
+impl DropRateSchedule {
+    pub fn new(mut points: Vec<(f64, f64)>) -> Self {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { points }
+    }
+
+    /// Sets the network drop rate according to the time of the next event.
+    pub fn apply(&self, sys: &mut System) {
+        let time = next_event_time(sys).unwrap_or(sys.time());
+        let rate = self
+            .points
+            .iter()
+            .take_while(|(at_time, _)| *at_time <= time)
+            .last()
+            .map_or(0., |(_, rate)| *rate);
+        sys.network().set_drop_rate(rate);
+    }
+}
+
+/// Distribution of the network message delays.
+///
+/// Real networks have heavy-tailed latencies, which break timeouts tuned for the typical delay.
+#[derive(Clone, Copy, Debug)]
+pub enum DelayDistribution {
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+    /// Normal distribution truncated at zero.
+    Normal {
+        mean: f64,
+        stddev: f64,
+    },
+    /// Exponential distribution with the mean delay `1 / lambda`.
+    Exponential {
+        lambda: f64,
+    },
+}
+
+impl DelayDistribution {
+    /// Samples the delay of a single message.
+    fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match *self {
+            DelayDistribution::Uniform { min, max } => rng.gen_range(min..=max),
+            DelayDistribution::Normal { mean, stddev } => {
+                // Box-Muller transform
+                let u1: f64 = 1. - rng.gen_range(0. ..1.);
+                let u2: f64 = rng.gen_range(0. ..1.);
+                let z = (-2. * u1.ln()).sqrt() * (2. * std::f64::consts::PI * u2).cos();
+                (mean + stddev * z).max(0.)
+            }
+            DelayDistribution::Exponential { lambda } => {
+                let u: f64 = rng.gen_range(0. ..1.);
+                -(1. - u).ln() / lambda
+            }
+        }
+    }
+}
+
+/// Prefix of the names of the timers holding the messages of the processes guarded by [`SampledDelays`].
+const HOLD_TIMER_PREFIX: &str = "__link_delay_";
+
+/// Delay distributions which are not uniform, shared by a [`LinkNetwork`] and the processes guarded by it.
+///
+/// The network samples delays uniformly, so the messages sent on a link with another distribution are held
+/// by the guarded sending process for a delay sampled for each message, and then sent with no network delay.
+/// The holding is done with timers named with [`HOLD_TIMER_PREFIX`], which are seen among the process timers.
+#[derive(Clone, Default)]
+struct SampledDelays {
+    state: Rc<RefCell<SampledDelaysState>>,
+}
+
+#[derive(Default)]
+struct SampledDelaysState {
+    /// Distribution of the links without their own delays, if it is not uniform.
+    default: Option<DelayDistribution>,
+    links: HashMap<(String, String), Option<DelayDistribution>>,
+    /// Nodes of the guarded processes.
+    proc_nodes: HashMap<String, String>,
+}
+
+impl SampledDelays {
+    fn sampled(delays: DelayDistribution) -> Option<DelayDistribution> {
+        match delays {
+            DelayDistribution::Uniform { .. } => None,
+            delays => Some(delays),
+        }
+    }
+
+    fn set_default(&self, delays: DelayDistribution) {
+        self.state.borrow_mut().default = Self::sampled(delays);
+    }
+
+    fn set_link(&self, from: &str, to: &str, delays: DelayDistribution) {
+        self.state
+            .borrow_mut()
+            .links
+            .insert((from.to_string(), to.to_string()), Self::sampled(delays));
+    }
+
+    fn is_guarded(&self) -> bool {
+        !self.state.borrow().proc_nodes.is_empty()
+    }
+
+    /// Returns the distribution of the message from the node to the process if it is sampled for each message.
+    fn link_delays(&self, from_node: &str, to_proc: &str) -> Option<DelayDistribution> {
+        let state = self.state.borrow();
+        let to_node = state.proc_nodes.get(to_proc)?;
+        match state.links.get(&(from_node.to_string(), to_node.clone())) {
+            Some(delays) => *delays,
+            None => state.default,
+        }
+    }
+
+    fn guard(&self, proc: Box<dyn Process>, proc_name: &str, node: &str, seed: u64) -> Box<dyn Process> {
+        self.state
+            .borrow_mut()
+            .proc_nodes
+            .insert(proc_name.to_string(), node.to_string());
+        boxed!(DelayedSendProcess {
+            proc,
+            proc_name: proc_name.to_string(),
+            node: node.to_string(),
+            delays: self.clone(),
+            rand: Pcg64::seed_from_u64(process_seed(seed, proc_name)),
+            held: HashMap::new(),
+            next_hold: 0,
+        })
+    }
+}
+
+/// Process guarded by [`SampledDelays`], which holds its messages for the sampled delays.
+#[derive(Clone)]
+struct DelayedSendProcess {
+    proc: Box<dyn Process>,
+    proc_name: String,
+    node: String,
+    delays: SampledDelays,
+    rand: Pcg64,
+    /// Held messages with their destinations by the number of the holding timer.
+    held: HashMap<u64, (Message, String)>,
+    next_hold: u64,
+}
+
+impl DelayedSendProcess {
+    fn handle(
+        &mut self,
+        ctx: &mut Context,
+        handler: impl FnOnce(&mut Box<dyn Process>, &mut Context) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut local_ctx = Context::basic(self.proc_name.clone(), ctx.time(), 0., self.rand.next_u64());
+        let res = handler(&mut self.proc, &mut local_ctx);
+        for action in local_ctx.actions() {
+            match action {
+                ProcessEvent::MessageSent { msg, dst, .. } => match self.delays.link_delays(&self.node, &dst) {
+                    Some(delays) => {
+                        let delay = delays.sample(&mut self.rand);
+                        ctx.set_timer_once(&format!("{HOLD_TIMER_PREFIX}{}", self.next_hold), delay);
+                        self.held.insert(self.next_hold, (msg, dst));
+                        self.next_hold += 1;
+                    }
+                    None => ctx.send(msg, dst),
+                },
+                ProcessEvent::LocalMessageSent { msg } => ctx.send_local(msg),
+                ProcessEvent::TimerSet { name, delay, behavior } => match behavior {
+                    TimerBehavior::SetOnce => ctx.set_timer_once(&name, delay),
+                    TimerBehavior::OverrideExisting => ctx.set_timer(&name, delay),
+                },
+                ProcessEvent::TimerCancelled { name } => ctx.cancel_timer(&name),
+            }
+        }
+        res
+    }
 }
 
-pub fn build_system(config: &TestConfig, measure_max_size: bool) -> System {
-    let mut sys = System::new(config.seed);
-    sys.add_node("sender-node");
-    sys.add_node("receiver-node");
+impl Process for DelayedSendProcess {
+    fn on_message(&mut self, msg: Message, from: String, ctx: &mut Context) -> Result<(), String> {
+        self.handle(ctx, |proc, ctx| proc.on_message(msg, from, ctx))
+    }
+
+    fn on_local_message(&mut self, msg: Message, ctx: &mut Context) -> Result<(), String> {
+        self.handle(ctx, |proc, ctx| proc.on_local_message(msg, ctx))
+    }
 
-    let sender_f = PyProcessFactory::new(config.impl_path, config.sender_class);
-    let mut sender = sender_f.build(("sender", "receiver"), config.seed);
-    if measure_max_size {
-        sender.set_max_size_freq(100);
+    fn on_timer(&mut self, timer: String, ctx: &mut Context) -> Result<(), String> {
+        let hold = timer
+            .strip_prefix(HOLD_TIMER_PREFIX)
+            .and_then(|hold| hold.parse().ok())
+            .and_then(|hold| self.held.remove(&hold));
+        match hold {
+            Some((msg, dst)) => {
+                ctx.send(msg, dst);
+                Ok(())
+            }
+            None => self.handle(ctx, |proc, ctx| proc.on_timer(timer, ctx)),
+        }
     }
-    sys.add_process("sender", boxed!(sender), "sender-node");
 
-    let receiver_f = PyProcessFactory::new(config.impl_path, config.receiver_class);
-    let mut receiver = receiver_f.build(("receiver",), config.seed);
-    if measure_max_size {
-        receiver.set_max_size_freq(100);
+    fn max_size(&mut self) -> u64 {
+        self.proc.max_size()
     }
-    sys.add_process("receiver", boxed!(receiver), "receiver-node");
 
-    sys
+    fn state(&self) -> Result<Rc<dyn ProcessState>, String> {
+        self.proc.state()
+    }
+
+    fn set_state(&mut self, state: Rc<dyn ProcessState>) -> Result<(), String> {
+        self.proc.set_state(state)
+    }
 }
 
-pub fn generate_message_texts(sys: &mut System, message_count: usize) -> Vec<String> {
-    if message_count == 5 {
-        ["distributed", "systems", "need", "some", "guarantees"]
-            .map(String::from)
-            .to_vec()
-    } else {
-        let mut messages = Vec::new();
-        for _i in 0..message_count {
-            let msg = if message_count == 10 {
-                format!("{}C", sys.gen_range(20..30))
+/// Delays, drop rate and ordering of a directed link, unset values fall back to the global ones.
+#[derive(Clone, Copy, Default)]
+struct LinkSettings {
+    delays: Option<DelayDistribution>,
+    drop_rate: Option<f64>,
+    ordered: Option<bool>,
+}
+
+/// Per-link network settings: a link from one node to another can be slower or lossier than the reverse one.
+///
+/// The network has only global settings, so they are switched between simulation steps according to the node
+/// handling the next event, which is the node sending messages during the step. This is exact as long as
+/// each node sends messages to a single other node, which is the case for sender and receiver, so the settings
+/// of the links from a node with several peers are not supported and [`LinkNetwork::apply_from`] panics on them.
+/// The nodes without their own link settings use the global ones regardless of the number of peers.
+/// [`LinkNetwork::apply`] should be called after each step (see [`send_messages_with`] and
+/// [`step_until_no_events_with`]), and [`LinkNetwork::apply_from`] before a local message is sent to a process
+/// (see [`send_messages_with_hooks`]).
+///
+/// Delays are sampled uniformly by the network by default. Other distributions are sampled for each message
+/// by the sending processes, so they require the system built with [`build_link_system`] (see [`SampledDelays`]).
+/// Messages are reordered by the sampled delays, unless the link delivers them in order: then the minimum delay
+/// is raised so that a new message does not arrive before the messages already in flight on the link,
+/// which is supported only for uniform delays.
+///
+/// With limited bandwidth the messages sent by a node are transmitted one by one, each taking its size divided
+/// by the bandwidth, and a new message waits for the transmission of the earlier ones before its delay starts.
+/// The transmission of the messages sent during a step is known only after the step, so they delay the messages
+/// of the later steps but not each other. A message is transmitted once regardless of its drop or duplication,
+/// just as its size is counted in [`Network::traffic`](anysystem::Network::traffic) once.
+pub struct LinkNetwork {
+    delays: DelayDistribution,
+    drop_rate: f64,
+    reorder: bool,
+    bandwidth: Option<f64>,
+    links: HashMap<(String, String), LinkSettings>,
+    /// Number of processed trace entries and the time each node completes the transmission of its messages.
+    transmission: RefCell<(usize, HashMap<String, f64>)>,
+    /// Number of processed trace entries and the extra delays of the next messages on the links.
+    next_delays: RefCell<(usize, HashMap<(String, String), f64>)>,
+    sampled_delays: SampledDelays,
+}
+
+impl LinkNetwork {
+    /// Creates the settings over the global network delays, which are set immediately, and the current drop rate.
+    pub fn new(sys: &mut System, delays: (f64, f64)) -> Self {
+        Self::with_sampled_delays(sys, delays, SampledDelays::default())
+    }
+
+    fn with_sampled_delays(sys: &mut System, delays: (f64, f64), sampled_delays: SampledDelays) -> Self {
+        sys.network().set_delays(delays.0, delays.1);
+        Self {
+            delays: DelayDistribution::Uniform {
+                min: delays.0,
+                max: delays.1,
+            },
+            drop_rate: sys.network().drop_rate(),
+            reorder: true,
+            bandwidth: None,
+            links: HashMap::new(),
+            transmission: RefCell::new((0, HashMap::new())),
+            next_delays: RefCell::new((0, HashMap::new())),
+            sampled_delays,
+        }
+    }
+
+    /// Sets whether the links without their own ordering setting may reorder messages (the default).
+    pub fn set_reorder(&mut self, reorder: bool) {
+        self.reorder = reorder;
+    }
+
+    /// Sets whether the link preserves the send order of messages or may reorder them.
+    pub fn deliver_in_order(&mut self, from: &str, to: &str, ordered: bool) {
+        self.link(from, to).ordered = Some(ordered);
+    }
+
+    /// Limits the number of bytes each node can transmit per unit of time, the bandwidth is unlimited by default.
+    pub fn set_bandwidth(&mut self, bytes_per_time: f64) {
+        self.bandwidth = Some(bytes_per_time);
+    }
+
+    /// Sets the drop rate of the links without their own drop rate, which is set in the network immediately.
+    pub fn set_drop_rate(&mut self, sys: &mut System, drop_rate: f64) {
+        sys.network().set_drop_rate(drop_rate);
+        self.drop_rate = drop_rate;
+    }
+
+    /// Sets the distribution of the delays of the links without their own delays, see [`LinkNetwork::apply`].
+    pub fn set_delay_distribution(&mut self, delays: DelayDistribution) {
+        self.sampled_delays.set_default(delays);
+        self.delays = delays;
+    }
+
+    pub fn set_link_delays(&mut self, from: &str, to: &str, min_delay: f64, max_delay: f64) {
+        let delays = DelayDistribution::Uniform {
+            min: min_delay,
+            max: max_delay,
+        };
+        self.set_link_delay_distribution(from, to, delays);
+    }
+
+    pub fn set_link_delay_distribution(&mut self, from: &str, to: &str, delays: DelayDistribution) {
+        self.sampled_delays.set_link(from, to, delays);
+        self.link(from, to).delays = Some(delays);
+    }
+
+    pub fn set_link_drop_rate(&mut self, from: &str, to: &str, drop_rate: f64) {
+        self.link(from, to).drop_rate = Some(drop_rate);
+    }
+
+    /// Adds `delay` to the delay of the next message sent on the link, e.g. to make it overtaken by the later ones.
+    ///
+    /// The delay applies to all messages sent on the link during the step sending the next message.
+    pub fn delay_next_message(&mut self, from: &str, to: &str, delay: f64) {
+        self.next_delays
+            .get_mut()
+            .1
+            .insert((from.to_string(), to.to_string()), delay);
+    }
+
+    fn link(&mut self, from: &str, to: &str) -> &mut LinkSettings {
+        self.links.entry((from.to_string(), to.to_string())).or_default()
+    }
+
+    /// Sets the network according to the node handling the next event.
+    pub fn apply(&self, sys: &mut System) {
+        let next_node = sys.sim().dump_events().first().and_then(|event| {
+            if let Some(e) = event.data.downcast_ref::<MessageReceived>() {
+                Some(e.dst_node.clone())
             } else {
-                sys.random_string(100)
-            };
-            messages.push(msg);
+                event
+                    .data
+                    .downcast_ref::<TimerFired>()
+                    .map(|e| sys.proc_node_name(&e.proc))
+            }
+        });
+        if let Some(node) = next_node {
+            self.apply_from(sys, &node);
         }
-        messages
     }
-}
 
-pub fn send_messages(sys: &mut System, message_count: usize) -> Vec<Message> {
-    let texts = generate_message_texts(sys, message_count);
-    let mut messages = Vec::new();
-    for text in texts {
-        let msg = Message::new("MESSAGE", &format!(r#"{{"text": "{text}"}}"#));
-        sys.send_local_message("sender", msg.clone());
-        if message_count <= 50 {
-            let steps = sys.gen_range(0..2);
-            if steps > 0 {
-                sys.steps(steps);
+    /// Sets the network for the messages sent by the node.
+    pub fn apply_from(&self, sys: &mut System, node: &str) {
+        let other_nodes: Vec<String> = sys.nodes().into_iter().filter(|n| n != node).collect();
+        let settings = match &other_nodes[..] {
+            [other] => self
+                .links
+                .get(&(node.to_string(), other.clone()))
+                .copied()
+                .unwrap_or_default(),
+            _ => {
+                let has_links = self.links.keys().any(|(from, _)| from == node)
+                    || self.next_delays.borrow().1.keys().any(|(from, _)| from == node);
+                assert!(
+                    !has_links,
+                    "Links of node {} can not be modeled: it has {} peers, while the network settings are global",
+                    node,
+                    other_nodes.len()
+                );
+                LinkSettings::default()
             }
-        } else {
-            let duration = sys.gen_range(0.0..2.0);
-            sys.step_for_duration(duration);
         };
-        messages.push(msg);
+        let ordered = settings.ordered.unwrap_or(!self.reorder);
+        let (mut min_delay, mut max_delay) = match settings.delays.unwrap_or(self.delays) {
+            DelayDistribution::Uniform { min, max } => (min, max),
+            delays => {
+                assert!(
+                    self.sampled_delays.is_guarded(),
+                    "Delays {:?} are sampled by the processes, build the system with build_link_system",
+                    delays
+                );
+                assert!(!ordered, "In-order delivery is supported only for uniform delays");
+                // the messages are held by the sending process for the sampled delays
+                (0., 0.)
+            }
+        };
+        if ordered {
+            // messages with equal delivery times are delivered in the order they were sent
+            let last_arrival = sys
+                .sim()
+                .dump_events()
+                .iter()
+                .filter(|event| {
+                    event
+                        .data
+                        .downcast_ref::<MessageReceived>()
+                        .is_some_and(|e| e.src_node == node)
+                })
+                .map(|event| event.time)
+                .fold(sys.time(), f64::max);
+            min_delay = min_delay.max(last_arrival - sys.time());
+            max_delay = max_delay.max(min_delay);
+        }
+        if let [other] = &other_nodes[..] {
+            let extra_delay = self.next_message_delay(sys, node, other);
+            min_delay += extra_delay;
+            max_delay += extra_delay;
+        }
+        if let Some(bandwidth) = self.bandwidth {
+            let queueing = (self.transmission_end(sys, node, bandwidth) - sys.time()).max(0.);
+            min_delay += queueing;
+            max_delay += queueing;
+        }
+        let mut net = sys.network();
+        net.set_delays(min_delay, max_delay);
+        net.set_drop_rate(settings.drop_rate.unwrap_or(self.drop_rate));
     }
-    messages
+
+    /// Returns the extra delay of the next message on the link, the delays of the links
+    /// with messages sent since the last call are used up.
+    fn next_message_delay(&self, sys: &System, from: &str, to: &str) -> f64 {
+        let (processed, delays) = &mut *self.next_delays.borrow_mut();
+        let logger = sys.logger();
+        let trace = logger.trace();
+        for entry in &trace[*processed..] {
+            if let LogEntry::MessageSent { src_node, dst_node, .. } = entry {
+                delays.remove(&(src_node.clone(), dst_node.clone()));
+            }
+        }
+        *processed = trace.len();
+        delays.get(&(from.to_string(), to.to_string())).copied().unwrap_or(0.)
+    }
+
+    /// Returns the time the node completes the transmission of the messages it has sent so far.
+    fn transmission_end(&self, sys: &System, node: &str, bandwidth: f64) -> f64 {
+        let (processed, ends) = &mut *self.transmission.borrow_mut();
+        let logger = sys.logger();
+        let trace = logger.trace();
+        for entry in &trace[*processed..] {
+            if let LogEntry::MessageSent {
+                time,
+                src_node,
+                dst_node,
+                msg,
+                ..
+            } = entry
+            {
+                if src_node != dst_node {
+                    let end = ends.entry(src_node.clone()).or_insert(0.);
+                    *end = end.max(*time) + msg.size() as f64 / bandwidth;
+                }
+            }
+        }
+        *processed = trace.len();
+        ends.get(node).copied().unwrap_or(0.)
+    }
+}
+
+/// Returns the timers scheduled by the process along with the time remaining until they fire, soonest first.
+pub fn pending_timers(sys: &System, proc: &str) -> Vec<(String, f64)> {
+    sys.sim()
+        .dump_events()
+        .into_iter()
+        .filter_map(|event| {
+            let e = event.data.downcast_ref::<TimerFired>()?;
+            (e.proc == proc).then(|| (e.timer.clone(), event.time - sys.time()))
+        })
+        .collect()
+}
+
+/// Returns the number of network messages sent by the process after the specified time.
+pub fn sent_message_count_after(sys: &System, proc: &str, time: f64) -> u64 {
+    sys.event_log(proc)
+        .iter()
+        .filter(|entry| entry.time > time && matches!(entry.event, ProcessEvent::MessageSent { .. }))
+        .count() as u64
+}
+
+/// Type of local messages used by a sender to confirm the delivery of a message to its user.
+/// A receipt carries the same data as the confirmed message.
+pub const RECEIPT_TIP: &str = "RECEIPT";
+
+/// Returns the local messages produced by the process along with the times they were produced.
+pub fn local_messages_with_time(sys: &System, proc: &str) -> Vec<(f64, Message)> {
+    sys.event_log(proc)
+        .into_iter()
+        .filter_map(|entry| match entry.event {
+            ProcessEvent::LocalMessageSent { msg } => Some((entry.time, msg)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the times of the first and the last local messages produced by the process if any.
+///
+/// The gap between them shows how well the deliveries are pipelined: a stop-and-wait protocol
+/// spreads the deliveries over the whole run, while a windowed one delivers messages in clusters.
+pub fn first_and_last_delivery_time(sys: &System, proc: &str) -> Option<(f64, f64)> {
+    let delivered = local_messages_with_time(sys, proc);
+    Some((delivered.first()?.0, delivered.last()?.0))
+}
+
+/// Returns how the local outbox of the process grows over time:
+/// one `(time, outbox_len)` sample per local message produced by the process.
+pub fn outbox_growth_profile(sys: &System, proc: &str) -> Vec<(f64, usize)> {
+    local_messages_with_time(sys, proc)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (time, _))| (time, i + 1))
+        .collect()
+}
+
+/// Checks that the outbox never stops growing for longer than `max_stall` while messages are expected,
+/// i.e. from `start` until the outbox reaches `expected_len`.
+///
+/// With ordered delivery a single lost message holds back all the following ones until it is retransmitted,
+/// so the longest stall shows how fast the protocol recovers from a loss.
+pub fn check_max_stall(profile: &[(f64, usize)], start: f64, expected_len: usize, max_stall: f64) -> TestResult {
+    let mut last_time = start;
+    for &(time, len) in profile {
+        assume!(
+            time - last_time <= max_stall,
+            format!(
+                "Outbox is stalled for {:.3} (from {last_time:.3} to {time:.3}) at length {}, max allowed stall is {max_stall}",
+                time - last_time,
+                len - 1
+            )
+        )?;
+        last_time = time;
+        if len >= expected_len {
+            return Ok(true);
+        }
+    }
+    Err(format!(
+        "Outbox reached only {} of {expected_len} messages",
+        profile.last().map_or(0, |&(_, len)| len)
+    ))
+}
+
+/// Returns the delivery receipts produced by the process along with the times they were produced.
+///
+/// Receipts are kept apart from other local messages of the process, which are left in its outbox.
+pub fn read_local_receipts(sys: &System, proc: &str) -> Vec<(f64, Message)> {
+    local_messages_with_time(sys, proc)
+        .into_iter()
+        .filter(|(_, msg)| msg.tip == RECEIPT_TIP)
+        .collect()
+}
+
+/// Checks that every receipt confirms a message that was already delivered at the time of the receipt,
+/// and no message is confirmed more times than it was delivered.
+pub fn check_receipts(receipts: &[(f64, Message)], delivered: &[(f64, Message)]) -> TestResult {
+    let mut confirmed_msg_count = HashMap::new();
+    for (time, receipt) in receipts {
+        let confirmed_count = confirmed_msg_count.entry(receipt.data.clone()).or_insert(0);
+        *confirmed_count += 1;
+        let delivered_count = delivered
+            .iter()
+            .filter(|(delivery_time, msg)| delivery_time <= time && msg.data == receipt.data)
+            .count();
+        assume!(
+            *confirmed_count <= delivered_count,
+            format!(
+                "Message {} is falsely confirmed at time {:.3} (confirmed count {} > delivered count {})",
+                receipt.data, time, confirmed_count, delivered_count
+            )
+        )?;
+    }
+    Ok(true)
 }
 
 pub fn check_delivered_messages(
@@ -155,15 +2396,68 @@ pub fn check_message_delivery_ordered(delivered: &[Message], sent: &[Message]) -
     Ok(true)
 }
 
+/// Checks that the messages of each sender are delivered in the order they were sent by it,
+/// while the messages of different senders may interleave arbitrarily.
+///
+/// The sent messages are given by sender, and the delivered ones are attributed to the senders by data.
+pub fn check_fifo_per_sender(delivered: &[Message], sent: &HashMap<String, Vec<Message>>) -> TestResult {
+    let mut senders: Vec<&String> = sent.keys().collect();
+    senders.sort();
+    for sender in senders {
+        let from_sender: Vec<Message> = delivered
+            .iter()
+            .filter(|msg| sent[sender].iter().any(|m| m.data == msg.data))
+            .cloned()
+            .collect();
+        check_message_delivery_ordered(&from_sender, &sent[sender])
+            .map_err(|e| format!("messages from {sender}: {e}"))?;
+    }
+    Ok(true)
+}
+
+/// Checks that no message is delivered before its causal predecessors (see [`send_messages_with_deps`]).
+///
+/// Delivered messages are matched to the sent ones by data, so repeated deliveries of a message
+/// (allowed by at least once guarantee) are ignored.
+pub fn check_causal_order(delivered: &[Message], sent: &[Message], deps: &CausalDeps) -> TestResult {
+    let mut sent_indices: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, msg) in sent.iter().enumerate() {
+        sent_indices.entry(&msg.data).or_default().push(i);
+    }
+    let mut is_delivered = vec![false; sent.len()];
+    for msg in delivered {
+        // the first not yet delivered message with the same data, otherwise it is a repeated delivery
+        let Some(&i) = sent_indices
+            .get(msg.data.as_str())
+            .and_then(|indices| indices.iter().find(|i| !is_delivered[**i]))
+        else {
+            continue;
+        };
+        if let Some(&dep) = deps[i].iter().find(|dep| !is_delivered[**dep]) {
+            return Err(format!(
+                "Causal order violation: {} is delivered before its predecessor {}",
+                msg.data, sent[dep].data
+            ));
+        }
+        is_delivered[i] = true;
+    }
+    Ok(true)
+}
+
 pub fn check_guarantees(sys: &mut System, sent: &[Message], config: &TestConfig) -> TestResult {
+    let delivered = sys.read_local_messages("receiver");
+    check_delivery(&delivered, sent, config)
+}
+
+/// Checks the messages delivered by the receiver against the expected guarantees.
+pub fn check_delivery(delivered: &[Message], sent: &[Message], config: &TestConfig) -> TestResult {
     let mut expected_msg_count = HashMap::new();
     for msg in sent {
         *expected_msg_count.entry(msg.data.clone()).or_insert(0) += 1;
     }
-    let delivered = sys.read_local_messages("receiver");
 
     // check that delivered messages have expected type and data
-    let delivered_msg_count = check_delivered_messages(&delivered, &expected_msg_count, &sent[0].tip)?;
+    let delivered_msg_count = check_delivered_messages(delivered, &expected_msg_count, &sent[0].tip)?;
 
     // check delivered message count according to expected guarantees
     if config.reliable {
@@ -173,11 +2467,225 @@ pub fn check_guarantees(sys: &mut System, sent: &[Message], config: &TestConfig)
         check_message_delivery_once(&delivered_msg_count, &expected_msg_count)?;
     }
     if config.ordered {
-        check_message_delivery_ordered(&delivered, sent)?;
+        check_message_delivery_ordered(delivered, sent)?;
     }
     Ok(true)
 }
 
+/// Returns the number of distinct sent messages delivered per unit of time.
+///
+/// In contrast to throughput, lost messages and extra deliveries of the same message are not counted,
+/// so only the useful work of the system is measured.
+pub fn goodput(delivered: &[Message], sent: &[Message], time: f64) -> f64 {
+    let mut remaining_msg_count = HashMap::new();
+    for msg in sent {
+        *remaining_msg_count.entry(&msg.data).or_insert(0) += 1;
+    }
+    let mut useful_count = 0;
+    for msg in delivered {
+        if let Some(count) = remaining_msg_count.get_mut(&msg.data).filter(|count| **count > 0) {
+            *count -= 1;
+            useful_count += 1;
+        }
+    }
+    useful_count as f64 / time
+}
+
+/// Simulated sizes of message texts, to test the overhead with large messages without generating huge texts.
+///
+/// The messages keep their real (short) texts, so the delivered data is unaffected. The network does not know about
+/// the hints, so they are applied when counting the traffic from the trace: a network message containing a hinted
+/// text is charged the hinted size instead of the text length, once for each hinted text it contains.
+#[derive(Clone, Debug, Default)]
+pub struct SizeHints {
+    sizes: HashMap<String, usize>,
+}
+
+impl SizeHints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Same as [`text_message`] but the text is counted in traffic as `size_bytes` bytes.
+    pub fn text_message(&mut self, text: &str, size_bytes: usize) -> Message {
+        self.sizes.insert(text.to_string(), size_bytes);
+        text_message(text)
+    }
+
+    /// Same as [`traffic_from`] but with the hinted sizes of the texts.
+    pub fn traffic_from(&self, sys: &System, proc: &str) -> u64 {
+        network_messages_sent_by(sys, proc)
+            .iter()
+            .map(|msg| self.size(msg) as u64)
+            .sum()
+    }
+
+    /// Returns the size of the message with the hinted sizes of the texts it contains.
+    pub fn size(&self, msg: &Message) -> usize {
+        self.sizes
+            .iter()
+            .filter(|(text, _)| msg.data.contains(text.as_str()))
+            .fold(msg.size(), |size, (text, hint)| size - text.len() + hint)
+    }
+
+    /// Returns the total hinted size of the texts, the payload the sender has to transmit at least once.
+    pub fn payload(&self) -> u64 {
+        self.sizes.values().map(|size| *size as u64).sum()
+    }
+}
+
+/// Byte accounting of messages sent between different nodes, collected from the system trace.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NetworkBytes {
+    pub sent: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub delivered: u64,
+}
+
+impl NetworkBytes {
+    pub fn collect(sys: &System) -> Self {
+        let mut bytes = Self::default();
+        let mut delivered_ids = HashMap::new();
+        for entry in sys.logger().trace() {
+            match entry {
+                LogEntry::MessageSent {
+                    src_node,
+                    dst_node,
+                    msg,
+                    ..
+                } if src_node != dst_node => bytes.sent += msg.size() as u64,
+                LogEntry::MessageDropped { msg, .. } => bytes.dropped += msg.size() as u64,
+                LogEntry::MessageReceived {
+                    msg_id,
+                    src_node,
+                    dst_node,
+                    msg,
+                    ..
+                } if src_node != dst_node => {
+                    let copies = delivered_ids.entry(msg_id).or_insert(0);
+                    *copies += 1;
+                    if *copies > 1 {
+                        bytes.duplicated += msg.size() as u64;
+                    }
+                    bytes.delivered += msg.size() as u64;
+                }
+                _ => {}
+            }
+        }
+        bytes
+    }
+
+    /// Returns the share of sent bytes which reached the destination, can exceed 1 due to duplication.
+    pub fn delivery_ratio(&self) -> f64 {
+        if self.sent == 0 {
+            return 1.;
+        }
+        self.delivered as f64 / self.sent as f64
+    }
+
+    /// Checks that the accounting agrees with the network traffic counter
+    /// and that no bytes are delivered beyond the sent, not dropped and duplicated ones.
+    pub fn check(&self, traffic: u64) -> TestResult {
+        assume_eq!(self.sent, traffic, "Sent bytes do not match network traffic")?;
+        assume!(
+            self.delivered <= self.sent - self.dropped + self.duplicated,
+            format!(
+                "Delivered {} bytes while sent {}, dropped {} and duplicated {}",
+                self.delivered, self.sent, self.dropped, self.duplicated
+            )
+        )
+    }
+}
+
+/// Returns the throughput of the run excluding the warmup: the time until the receiver delivers the first
+/// `warmup` messages, which includes the connection setup, and these messages are not counted.
+///
+/// Without warmup or if fewer messages are delivered, it is the plain throughput `message_count / time`.
+pub fn warm_throughput(sys: &System, message_count: usize, warmup: usize) -> f64 {
+    let delivered = local_messages_with_time(sys, "receiver");
+    match warmup.checked_sub(1).and_then(|i| delivered.get(i)) {
+        Some((start, _)) if warmup < message_count => (message_count - warmup) as f64 / (sys.time() - start),
+        _ => message_count as f64 / sys.time(),
+    }
+}
+
+/// Returns the limits of sender memory, receiver memory, network message count, network traffic and throughput
+/// that the solution for the guarantee should meet when sending `message_count` messages.
+pub fn overhead_limits(guarantee: &str, faulty: bool, message_count: usize) -> (u64, u64, u64, u64, f64) {
+    match guarantee {
+        "AMO" => match message_count {
+            100 => {
+                if !faulty {
+                    (800, 1500, 100, 20000, 0.6)
+                } else {
+                    (800, 3500, 100, 20000, 0.6)
+                }
+            }
+            1000 => {
+                if !faulty {
+                    (800, 1500, 1000, 200000, 0.6)
+                } else {
+                    (800, 30000, 1000, 200000, 0.6)
+                }
+            }
+            _ => (u64::MAX, u64::MAX, u64::MAX, u64::MAX, 0.),
+        },
+        "ALO" => match message_count {
+            100 => {
+                if !faulty {
+                    (2200, 600, 200, 20000, 0.6)
+                } else {
+                    (12000, 600, 500, 40000, 0.6)
+                }
+            }
+            1000 => {
+                if !faulty {
+                    (4200, 600, 2000, 200000, 0.6)
+                } else {
+                    (15000, 600, 5000, 400000, 0.6)
+                }
+            }
+            _ => (u64::MAX, u64::MAX, u64::MAX, u64::MAX, 0.),
+        },
+        "EO" => match message_count {
+            100 => {
+                if !faulty {
+                    (2200, 1500, 200, 20000, 0.6)
+                } else {
+                    (12000, 2200, 500, 40000, 0.6)
+                }
+            }
+            1000 => {
+                if !faulty {
+                    (4200, 1500, 2000, 200000, 0.6)
+                } else {
+                    (15000, 2200, 5000, 400000, 0.6)
+                }
+            }
+            _ => (u64::MAX, u64::MAX, u64::MAX, u64::MAX, 0.),
+        },
+        "EOO" => match message_count {
+            100 => {
+                if !faulty {
+                    (3500, 1200, 200, 25000, 0.4)
+                } else {
+                    (30000, 6000, 500, 45000, 0.4)
+                }
+            }
+            1000 => {
+                if !faulty {
+                    (6000, 1200, 2000, 250000, 0.4)
+                } else {
+                    (200000, 10000, 5000, 450000, 0.4)
+                }
+            }
+            _ => (u64::MAX, u64::MAX, u64::MAX, u64::MAX, 0.),
+        },
+        _ => (u64::MAX, u64::MAX, u64::MAX, u64::MAX, 0.),
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn check_overhead(
     guarantee: &str,
@@ -188,98 +2696,161 @@ pub fn check_overhead(
     net_message_count: u64,
     net_traffic: u64,
     throughput: f64,
+    goodput: f64,
+    metric: OverheadMetric,
 ) -> TestResult {
     let (sender_mem_limit, receiver_mem_limit, net_message_count_limit, net_traffic_limit, throughput_limit) =
-        match guarantee {
-            "AMO" => match message_count {
-                100 => {
-                    if !faulty {
-                        (800, 1500, 100, 20000, 0.6)
-                    } else {
-                        (800, 3500, 100, 20000, 0.6)
-                    }
-                }
-                1000 => {
-                    if !faulty {
-                        (800, 1500, 1000, 200000, 0.6)
-                    } else {
-                        (800, 30000, 1000, 200000, 0.6)
-                    }
-                }
-                _ => (u64::MAX, u64::MAX, u64::MAX, u64::MAX, 0.),
-            },
-            "ALO" => match message_count {
-                100 => {
-                    if !faulty {
-                        (2200, 600, 200, 20000, 0.6)
-                    } else {
-                        (12000, 600, 500, 40000, 0.6)
-                    }
-                }
-                1000 => {
-                    if !faulty {
-                        (4200, 600, 2000, 200000, 0.6)
-                    } else {
-                        (15000, 600, 5000, 400000, 0.6)
-                    }
-                }
-                _ => (u64::MAX, u64::MAX, u64::MAX, u64::MAX, 0.),
-            },
-            "EO" => match message_count {
-                100 => {
-                    if !faulty {
-                        (2200, 1500, 200, 20000, 0.6)
-                    } else {
-                        (12000, 2200, 500, 40000, 0.6)
-                    }
-                }
-                1000 => {
-                    if !faulty {
-                        (4200, 1500, 2000, 200000, 0.6)
-                    } else {
-                        (15000, 2200, 5000, 400000, 0.6)
-                    }
-                }
-                _ => (u64::MAX, u64::MAX, u64::MAX, u64::MAX, 0.),
-            },
-            "EOO" => match message_count {
-                100 => {
-                    if !faulty {
-                        (3500, 1200, 200, 25000, 0.4)
-                    } else {
-                        (30000, 6000, 500, 45000, 0.4)
-                    }
-                }
-                1000 => {
-                    if !faulty {
-                        (6000, 1200, 2000, 250000, 0.4)
-                    } else {
-                        (200000, 10000, 5000, 450000, 0.4)
-                    }
-                }
-                _ => (u64::MAX, u64::MAX, u64::MAX, u64::MAX, 0.),
-            },
-            _ => (u64::MAX, u64::MAX, u64::MAX, u64::MAX, 0.),
+        overhead_limits(guarantee, faulty, message_count);
+    if metric.includes(OverheadMetric::Memory) {
+        assume!(
+            sender_mem <= sender_mem_limit,
+            format!("Sender memory > {}", sender_mem_limit)
+        )?;
+        assume!(
+            receiver_mem <= receiver_mem_limit,
+            format!("Receiver memory > {}", receiver_mem_limit)
+        )?;
+    }
+    if metric.includes(OverheadMetric::Traffic) {
+        assume!(
+            net_message_count <= net_message_count_limit,
+            format!("Message count > {}", net_message_count_limit)
+        )?;
+        assume!(
+            net_traffic <= net_traffic_limit,
+            format!("Traffic > {}", net_traffic_limit)
+        )?;
+    }
+    if metric.includes(OverheadMetric::Throughput) {
+        assume!(
+            throughput >= throughput_limit,
+            format!("Throughput < {}", throughput_limit)
+        )?;
+        // at most once is allowed to lose messages in faulty network
+        let goodput_limit = if guarantee == "AMO" && faulty {
+            throughput_limit / 2.
+        } else {
+            throughput_limit
         };
-    assume!(
-        sender_mem <= sender_mem_limit,
-        format!("Sender memory > {}", sender_mem_limit)
-    )?;
-    assume!(
-        receiver_mem <= receiver_mem_limit,
-        format!("Receiver memory > {}", receiver_mem_limit)
-    )?;
-    assume!(
-        net_message_count <= net_message_count_limit,
-        format!("Message count > {}", net_message_count_limit)
-    )?;
-    assume!(
-        net_traffic <= net_traffic_limit,
-        format!("Traffic > {}", net_traffic_limit)
-    )?;
-    assume!(
-        throughput >= throughput_limit,
-        format!("Throughput < {}", throughput_limit)
-    )?;
+        assume!(goodput >= goodput_limit, format!("Goodput < {}", goodput_limit))?;
+    }
     Ok(true)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+    use std::ops::Range;
+
+    use rand::distributions::uniform::SampleUniform;
+
+    use super::*;
+
+    /// Sender sending a message on start and then every `period` units of time until `count` messages are sent,
+    /// or forever if `count` is `None`.
+    #[derive(Clone)]
+    struct PeriodicSender {
+        period: f64,
+        count: Option<u64>,
+        sent: u64,
+    }
+
+    impl PeriodicSender {
+        fn send(&mut self, ctx: &mut Context) {
+            if self.count.map_or(true, |count| self.sent < count) {
+                ctx.send(Message::new("PING", &self.sent.to_string()), "receiver".to_string());
+                self.sent += 1;
+                ctx.set_timer("send", self.period);
+            }
+        }
+    }
+
+    impl Process for PeriodicSender {
+        fn on_message(&mut self, _msg: Message, _from: String, _ctx: &mut Context) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_local_message(&mut self, _msg: Message, ctx: &mut Context) -> Result<(), String> {
+            self.send(ctx);
+            Ok(())
+        }
+
+        fn on_timer(&mut self, _timer: String, ctx: &mut Context) -> Result<(), String> {
+            self.send(ctx);
+            Ok(())
+        }
+
+        fn max_size(&mut self) -> u64 {
+            0
+        }
+
+        fn state(&self) -> Result<Rc<dyn ProcessState>, String> {
+            Ok(Rc::new(self.sent.to_string()))
+        }
+
+        fn set_state(&mut self, _state: Rc<dyn ProcessState>) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn periodic_sender_system(count: Option<u64>) -> System {
+        let mut sys = System::new(0);
+        sys.add_node("sender-node");
+        sys.add_node("receiver-node");
+        let sender = PeriodicSender {
+            period: 3.,
+            count,
+            sent: 0,
+        };
+        sys.add_process("sender", boxed!(sender.clone()), "sender-node");
+        sys.add_process("receiver", boxed!(sender), "receiver-node");
+        sys.send_local_message("sender", Message::new("START", ""));
+        sys
+    }
+
+    /// Checks that the numbers generated by two systems with the same seed are in the range and the same.
+    fn check_gen_range<T>(range: Range<T>)
+    where
+        T: SampleUniform + PartialOrd + Copy + Debug,
+    {
+        let (mut first, mut second) = (System::new(42), System::new(42));
+        for _ in 0..100 {
+            let value: T = first.gen_range(range.clone());
+            assert!(range.contains(&value), "{value:?} is out of range {range:?}");
+            assert_eq!(
+                value,
+                second.gen_range(range.clone()),
+                "different numbers in range {range:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn gen_range_of_numeric_types() {
+        check_gen_range::<i32>(-100..100);
+        check_gen_range::<i32>(i32::MIN..i32::MAX);
+        check_gen_range::<u64>(0..10);
+        check_gen_range::<u64>(u64::MAX / 2..u64::MAX);
+        check_gen_range::<usize>(0..1000);
+        check_gen_range::<f64>(0.0..2.0);
+        check_gen_range::<f64>(-1e9..1e9);
+    }
+
+    #[test]
+    fn sender_idle_after_stopping() {
+        let mut sys = periodic_sender_system(Some(2));
+        sys.step_for_duration(10.);
+        assert_eq!(assert_sender_idle_after(&mut sys, 10.), Ok(true));
+    }
+
+    #[test]
+    fn sender_not_idle_if_never_stopping() {
+        let mut sys = periodic_sender_system(None);
+        sys.step_for_duration(10.);
+        let res = assert_sender_idle_after(&mut sys, 10.);
+        assert!(
+            res.is_err_and(|e| e.starts_with("Sender is not idle: sent PING")),
+            "{res:?}"
+        );
+    }
+}
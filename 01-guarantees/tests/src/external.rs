@@ -0,0 +1,275 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::rc::Rc;
+
+use serde_json::ser::Formatter;
+use serde_json::{json, Value};
+
+use anysystem::{Context, Message, Process, ProcessState};
+
+use crate::common::message_from_json;
+
+/// Factory of processes running in a separate runtime and talking to the tests over stdin and stdout.
+///
+/// The solution is started once per process as `<program> <args...>`, and is sent one JSON request per line,
+/// to each of which it replies with one JSON line. The first request creates the process:
+///
+/// ```text
+/// {"method": "init", "class": "AtLeastOnceSender", "args": ["sender", "receiver"], "kwargs": {}, "seed": 123}
+/// ```
+///
+/// Here `kwargs` are the keyword arguments of the constructor given with `--kwarg`, all values are strings.
+///
+/// The handlers of the process are called with `on_message` (with `msg`, `from` and `time`), `on_local_message`
+/// (with `msg` and `time`) and `on_timer` (with `timer` and `time`), where a message is `{"type": ..., "data": {...}}`.
+/// The reply lists the actions made by the handler in order:
+///
+/// ```text
+/// {"actions": [{"send": {"msg": {"type": "DATA", "data": {"seq": 1}}, "to": "receiver"}},
+///              {"send_local": {"type": "MESSAGE", "data": {"text": "..."}}},
+///              {"set_timer": {"name": "rtx", "delay": 6.5}}, {"set_timer_once": {"name": "rtx", "delay": 6.5}},
+///              {"cancel_timer": "rtx"}]}
+/// ```
+///
+/// Model checking and receiver restarts additionally use `get_state` (replied with `{"state": "..."}`)
+/// and `set_state` (with `state`), and the overhead tests use `size` (replied with `{"size": N}`, the size of
+/// the process data in bytes). A reply with `error` fails the handler.
+pub struct ExternalProcessFactory {
+    program: String,
+    args: Vec<String>,
+    class: String,
+}
+
+impl ExternalProcessFactory {
+    /// Runs a Java solution from a source file with `java <impl_path>`.
+    pub fn java(impl_path: &str, class: &str) -> Self {
+        Self {
+            program: "java".to_string(),
+            args: vec![impl_path.to_string()],
+            class: class.to_string(),
+        }
+    }
+
+    /// Runs a Go solution from a source file with `go run <impl_path>`.
+    pub fn go(impl_path: &str, class: &str) -> Self {
+        Self {
+            program: "go".to_string(),
+            args: vec!["run".to_string(), impl_path.to_string()],
+            class: class.to_string(),
+        }
+    }
+
+    /// Creates a process instance with specified positional and keyword arguments and random seed.
+    ///
+    /// With `measure_max_size` the size of the process data is requested after each handler
+    /// to find its maximum, otherwise only when the maximum is read.
+    pub fn build(
+        &self,
+        args: &[&str],
+        kwargs: &HashMap<String, String>,
+        seed: u64,
+        measure_max_size: bool,
+    ) -> ExternalProcess {
+        let kwargs: BTreeMap<_, _> = kwargs.iter().collect();
+        let init = json!({"method": "init", "class": self.class, "args": args, "kwargs": kwargs, "seed": seed});
+        let command = Rc::new((self.program.clone(), self.args.clone(), init));
+        let mut proc = ExternalProcess::start(command).unwrap();
+        proc.measure_max_size = measure_max_size;
+        proc
+    }
+}
+
+/// Process implementation backed by a process of another runtime, see [`ExternalProcessFactory`].
+pub struct ExternalProcess {
+    /// Program, its arguments and the init request.
+    command: Rc<(String, Vec<String>, Value)>,
+    child: Child,
+    pipes: RefCell<(ChildStdin, BufReader<ChildStdout>)>,
+    max_size: u64,
+    measure_max_size: bool,
+}
+
+impl ExternalProcess {
+    fn start(command: Rc<(String, Vec<String>, Value)>) -> Result<Self, String> {
+        let (program, args, init) = &*command;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Cannot start {program}: {e}"))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let init = init.clone();
+        let proc = Self {
+            command,
+            child,
+            pipes: RefCell::new((stdin, stdout)),
+            max_size: 0,
+            measure_max_size: false,
+        };
+        proc.request(init)?;
+        Ok(proc)
+    }
+
+    fn request(&self, request: Value) -> Result<Value, String> {
+        let (stdin, stdout) = &mut *self.pipes.borrow_mut();
+        writeln!(stdin, "{request}").map_err(|e| format!("Cannot write to process: {e}"))?;
+        let mut line = String::new();
+        match stdout.read_line(&mut line) {
+            Ok(0) => return Err("Process exited".to_string()),
+            Ok(_) => {}
+            Err(e) => return Err(format!("Cannot read from process: {e}")),
+        }
+        let reply: Value = serde_json::from_str(&line).map_err(|e| format!("Bad reply of process {line}: {e}"))?;
+        match reply.get("error") {
+            Some(error) => Err(error.as_str().map_or_else(|| error.to_string(), String::from)),
+            None => Ok(reply),
+        }
+    }
+
+    fn get_state(&self) -> Result<String, String> {
+        let reply = self.request(json!({"method": "get_state"}))?;
+        let state = reply["state"]
+            .as_str()
+            .ok_or(format!("Bad state of process: {reply}"))?;
+        Ok(state.to_string())
+    }
+
+    /// Calls the handler and performs the actions it made.
+    fn handle(&mut self, mut request: Value, ctx: &mut Context) -> Result<(), String> {
+        request["time"] = json!(ctx.time());
+        let reply = self.request(request)?;
+        let actions = reply["actions"].as_array().cloned().unwrap_or_default();
+        for action in actions {
+            let bad_action = || format!("Bad action of process: {action}");
+            let (name, args) = action
+                .as_object()
+                .and_then(|action| action.iter().next())
+                .ok_or_else(bad_action)?;
+            match name.as_str() {
+                "send" => {
+                    let to = args["to"].as_str().ok_or_else(bad_action)?;
+                    ctx.send(from_json(&args["msg"]).ok_or_else(bad_action)?, to.to_string());
+                }
+                "send_local" => ctx.send_local(from_json(args).ok_or_else(bad_action)?),
+                "set_timer" | "set_timer_once" => {
+                    let timer = args["name"].as_str().ok_or_else(bad_action)?;
+                    let delay = args["delay"].as_f64().ok_or_else(bad_action)?;
+                    if name == "set_timer" {
+                        ctx.set_timer(timer, delay);
+                    } else {
+                        ctx.set_timer_once(timer, delay);
+                    }
+                }
+                "cancel_timer" => ctx.cancel_timer(args.as_str().ok_or_else(bad_action)?),
+                _ => return Err(bad_action()),
+            }
+        }
+        if self.measure_max_size {
+            self.update_max_size();
+        }
+        Ok(())
+    }
+
+    fn update_max_size(&mut self) {
+        // the size is optional, so the processes not reporting it are considered empty
+        if let Ok(reply) = self.request(json!({"method": "size"})) {
+            self.max_size = self.max_size.max(reply["size"].as_u64().unwrap_or(0));
+        }
+    }
+}
+
+fn to_json(msg: &Message) -> Value {
+    json!({"type": msg.tip, "data": serde_json::from_str::<Value>(&msg.data).unwrap_or(Value::Null)})
+}
+
+fn from_json(msg: &Value) -> Option<Message> {
+    Some(message_from_json(msg["type"].as_str()?, &msg["data"]))
+}
+
+/// Formats JSON as `json.dumps` in Python does, so that the data of the messages sent by the solutions
+/// is the same for all languages, e.g. `{"text": "distributed"}`.
+pub struct PythonFormatter;
+
+impl Formatter for PythonFormatter {
+    fn begin_array_value<W: ?Sized + Write>(&mut self, writer: &mut W, first: bool) -> io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(&mut self, writer: &mut W, first: bool) -> io::Result<()> {
+        self.begin_array_value(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b": ")
+    }
+
+    /// Escapes the characters outside of printable ASCII, as `json.dumps` does with the default `ensure_ascii`.
+    fn write_string_fragment<W: ?Sized + Write>(&mut self, writer: &mut W, fragment: &str) -> io::Result<()> {
+        for c in fragment.chars() {
+            if (' '..='~').contains(&c) {
+                writer.write_all(c.encode_utf8(&mut [0; 4]).as_bytes())?;
+            } else {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    write!(writer, "\\u{unit:04x}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Process for ExternalProcess {
+    fn on_message(&mut self, msg: Message, from: String, ctx: &mut Context) -> Result<(), String> {
+        self.handle(json!({"method": "on_message", "msg": to_json(&msg), "from": from}), ctx)
+    }
+
+    fn on_local_message(&mut self, msg: Message, ctx: &mut Context) -> Result<(), String> {
+        self.handle(json!({"method": "on_local_message", "msg": to_json(&msg)}), ctx)
+    }
+
+    fn on_timer(&mut self, timer: String, ctx: &mut Context) -> Result<(), String> {
+        self.handle(json!({"method": "on_timer", "timer": timer}), ctx)
+    }
+
+    fn max_size(&mut self) -> u64 {
+        self.update_max_size();
+        self.max_size
+    }
+
+    fn state(&self) -> Result<Rc<dyn ProcessState>, String> {
+        Ok(Rc::new(self.get_state()?))
+    }
+
+    fn set_state(&mut self, state: Rc<dyn ProcessState>) -> Result<(), String> {
+        let state = state.downcast_rc::<String>().map_err(|_| "Bad process state")?;
+        self.request(json!({"method": "set_state", "state": *state}))?;
+        Ok(())
+    }
+}
+
+impl Clone for ExternalProcess {
+    /// Starts a new process of the runtime with the same state, so that the copies do not affect each other.
+    fn clone(&self) -> Self {
+        let mut proc = Self::start(self.command.clone()).unwrap();
+        let state = self.get_state().unwrap();
+        proc.request(json!({"method": "set_state", "state": state})).unwrap();
+        proc.max_size = self.max_size;
+        proc.measure_max_size = self.measure_max_size;
+        proc
+    }
+}
+
+impl Drop for ExternalProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::common::DelayDistribution;
+use crate::overhead::GUARANTEES;
+
+/// Test scenario loaded from a data file at grading time instead of being defined in the code.
+///
+/// A scenario is stored as a text file with `.test` extension, one `key = value` pair per line:
+///
+/// ```text
+/// # heavy loss with duplication
+/// name = HEAVY LOSS
+/// guarantees = ALO EO EOO
+/// messages = 20
+/// delays = 1 3
+/// drop_rate = 0.5
+/// dupl_rate = 0.3
+/// corrupt_rate = 0.1
+/// max_sent = 200
+/// weight = 0.5
+/// senders = 2
+/// receivers = 1
+/// kwarg = window=8
+/// ```
+///
+/// Only `name` is required. By default a scenario is run for all guarantees with 5 messages, the default network
+/// and the seed of the suite. The messages are generated as in other tests (see [`generate_message_texts`](crate::common::generate_message_texts)).
+/// With `corrupt_rate` the network replaces all strings in the data of a message (both keys and values) with empty ones
+/// with this probability, keeping its type (see `Network::set_corrupt_rate`), so the solution has to detect corrupted
+/// messages and treat them as lost.
+/// The `delays` are sampled uniformly from the given range (or set to a single value), other distributions are set
+/// with `delays = normal MEAN STDDEV` or `delays = exponential LAMBDA` (see [`DelayDistribution`]).
+/// If `max_sent` is set, the sender is not allowed to send more network messages.
+/// With several `senders` or `receivers` the system is built with [`build_multi_system`](crate::common::build_multi_system),
+/// each sender gets `messages` messages, and `max_sent` limits the messages sent by all senders together.
+/// Such systems support only uniform delays.
+/// Each `kwarg = KEY=VALUE` line passes a keyword argument to the constructors of the solution processes
+/// in addition to the ones given with `--kwarg` (and overriding them), so that scenarios can tune the solution.
+/// A failed scenario reduces the score by its `weight` (1 by default).
+#[derive(Clone, Debug)]
+pub struct HiddenTest {
+    pub name: String,
+    pub guarantees: Vec<String>,
+    pub message_count: usize,
+    pub delays: Option<DelayDistribution>,
+    pub drop_rate: f64,
+    pub dupl_rate: f64,
+    pub corrupt_rate: f64,
+    pub seed: Option<u64>,
+    pub max_sent: Option<u64>,
+    pub weight: f32,
+    pub senders: usize,
+    pub receivers: usize,
+    pub kwargs: HashMap<String, String>,
+}
+
+/// Keys of a scenario configuring the network.
+pub const NETWORK_KEYS: [&str; 4] = ["delays", "drop_rate", "dupl_rate", "corrupt_rate"];
+
+impl HiddenTest {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut test = Self {
+            name: String::new(),
+            guarantees: GUARANTEES.map(String::from).to_vec(),
+            message_count: 5,
+            delays: None,
+            drop_rate: 0.,
+            dupl_rate: 0.,
+            corrupt_rate: 0.,
+            seed: None,
+            max_sent: None,
+            weight: 1.,
+            senders: 1,
+            receivers: 1,
+            kwargs: HashMap::new(),
+        };
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or(format!("Expected key = value: {line}"))?;
+            let bad_value = || format!("Bad value of {key}: {value}");
+            match key {
+                "name" => test.name = value.to_string(),
+                "guarantees" => {
+                    test.guarantees = value.split_whitespace().map(String::from).collect();
+                    if let Some(g) = test.guarantees.iter().find(|g| !GUARANTEES.contains(&g.as_str())) {
+                        return Err(format!("Unknown guarantee: {g}"));
+                    }
+                }
+                "messages" => test.message_count = value.parse().map_err(|_| bad_value())?,
+                "delays" => {
+                    let mut words: Vec<&str> = value.split_whitespace().collect();
+                    let distribution = match words.first() {
+                        Some(&("normal" | "exponential")) => words.remove(0),
+                        _ => "uniform",
+                    };
+                    let params: Vec<f64> = words
+                        .iter()
+                        .map(|param| param.parse().map_err(|_| bad_value()))
+                        .collect::<Result<_, _>>()?;
+                    test.delays = Some(match (distribution, &params[..]) {
+                        ("uniform", [delay]) => DelayDistribution::Uniform {
+                            min: *delay,
+                            max: *delay,
+                        },
+                        ("uniform", [min, max]) => DelayDistribution::Uniform { min: *min, max: *max },
+                        ("normal", [mean, stddev]) => DelayDistribution::Normal {
+                            mean: *mean,
+                            stddev: *stddev,
+                        },
+                        ("exponential", [lambda]) => DelayDistribution::Exponential { lambda: *lambda },
+                        _ => return Err(bad_value()),
+                    });
+                }
+                "drop_rate" => test.drop_rate = value.parse().map_err(|_| bad_value())?,
+                "dupl_rate" => test.dupl_rate = value.parse().map_err(|_| bad_value())?,
+                "corrupt_rate" => test.corrupt_rate = value.parse().map_err(|_| bad_value())?,
+                "seed" => test.seed = Some(value.parse().map_err(|_| bad_value())?),
+                "max_sent" => test.max_sent = Some(value.parse().map_err(|_| bad_value())?),
+                "weight" => test.weight = value.parse().map_err(|_| bad_value())?,
+                "senders" | "receivers" => {
+                    let count = value.parse().ok().filter(|count| *count > 0).ok_or_else(bad_value)?;
+                    if key == "senders" {
+                        test.senders = count;
+                    } else {
+                        test.receivers = count;
+                    }
+                }
+                "kwarg" => {
+                    let (name, arg) = value.split_once('=').ok_or_else(bad_value)?;
+                    test.kwargs.insert(name.trim().to_string(), arg.trim().to_string());
+                }
+                _ => return Err(format!("Unknown key: {key}")),
+            }
+        }
+        if test.name.is_empty() {
+            return Err("Test name is not set".to_string());
+        }
+        if test.is_multi() && !matches!(test.delays, None | Some(DelayDistribution::Uniform { .. })) {
+            return Err("Several senders or receivers support only uniform delays".to_string());
+        }
+        Ok(test)
+    }
+
+    pub fn runs_for(&self, guarantee: &str) -> bool {
+        self.guarantees.iter().any(|g| g == guarantee)
+    }
+
+    /// Checks if the scenario has more than one sender or receiver.
+    pub fn is_multi(&self) -> bool {
+        self.senders > 1 || self.receivers > 1
+    }
+}
+
+/// Loads all scenarios from `.test` files in the directory ordered by file name.
+pub fn load_hidden_tests(dir: &str) -> Result<Vec<HiddenTest>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Cannot read hidden tests from {dir}: {e}"))?;
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "test"))
+        .collect();
+    paths.sort();
+    paths.iter().map(|path| load_hidden_test(path)).collect()
+}
+
+fn load_hidden_test(path: &Path) -> Result<HiddenTest, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    HiddenTest::parse(&text).map_err(|e| format!("Bad hidden test {}: {e}", path.display()))
+}
@@ -1,18 +1,39 @@
 mod common;
+mod external;
+mod hidden;
+mod mc_scenario;
+mod overhead;
+mod predicates;
+mod schedule;
+mod score;
+mod source_check;
+mod suite;
 mod tests;
 mod tests_mc;
 
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
+use std::fs;
 use std::io::Write;
+use std::process;
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use env_logger::Builder;
 use log::LevelFilter;
+use regex::Regex;
+use serde_json::json;
 
-use anysystem::test::{TestResult, TestSuite};
+use anysystem::test::TestResult;
 
-use crate::common::TestConfig;
+use crate::common::{take_traces, ChaosParams, TestConfig};
+use crate::hidden::{load_hidden_tests, HiddenTest, NETWORK_KEYS};
+use crate::mc_scenario::{load_mc_scenario, McScenario};
+use crate::overhead::{print_overhead_summary, take_overhead_rows, write_overhead_csv, OverheadMetric, GUARANTEES};
+use crate::schedule::Schedule;
+use crate::score::{load_score_config, ScoreConfig};
+use crate::source_check::{load_source_check_config, SourceCheck};
+use crate::suite::{TestSuite, TEST_PROCESS_VAR};
 use crate::tests::*;
 use crate::tests_mc::*;
 
@@ -20,10 +41,14 @@ use crate::tests_mc::*;
 #[derive(Parser, Debug)]
 #[clap(about, long_about = None)]
 struct Args {
-    /// Path to Python file with solution
+    /// Path to file with solution (Python, Java or Go)
     #[clap(long = "impl", short = 'i', default_value = "solution/guarantees.py")]
     solution_path: String,
 
+    /// Keyword argument passed to the constructors of the solution processes, can be repeated
+    #[clap(long = "kwarg", value_name = "KEY=VALUE", multiple_occurrences = true)]
+    kwargs: Vec<String>,
+
     /// Test to run (optional)
     #[clap(long = "test", short)]
     test: Option<String>,
@@ -32,8 +57,16 @@ struct Args {
     #[clap(long, short)]
     debug: bool,
 
+    /// Print only execution trace events mentioning the given process or node, can be repeated
+    #[clap(long, value_name = "NAME", multiple_occurrences = true, requires = "debug")]
+    debug_proc: Vec<String>,
+
+    /// Run all tests with names matching the regular expression
+    #[clap(long, conflicts_with = "test")]
+    filter: Option<String>,
+
     /// Guarantee to check
-    #[clap(long, short, possible_values = ["AMO", "ALO", "EO", "EOO"])]
+    #[clap(long, short, possible_values = GUARANTEES)]
     guarantee: Option<String>,
 
     /// Random seed used in tests
@@ -44,41 +77,301 @@ struct Args {
     #[clap(long, short, default_value = "0")]
     monkeys: u32,
 
+    /// Number of threads running chaos monkey runs in parallel
+    #[clap(long, default_value = "1")]
+    chaos_jobs: u32,
+
+    /// Number of threads running tests in parallel
+    #[clap(long, short, default_value = "1")]
+    jobs: usize,
+
+    /// Stop each test after this real time in seconds and consider it failed, the tests are run in child processes
+    #[clap(long)]
+    test_timeout: Option<f64>,
+
+    /// Maximum number of simulation steps in a test run before it is considered infinite
+    #[clap(long, default_value = "100000")]
+    max_steps: u64,
+
+    /// Finish test runs once no messages are delivered for this time instead of waiting for no events
+    #[clap(long)]
+    settle: Option<f64>,
+
+    /// Load the solution module once and reuse it in all tests instead of loading it for each test
+    #[clap(long)]
+    prewarm: bool,
+
     /// Run overhead tests
     #[clap(long, short)]
     overhead: bool,
 
+    /// Check and report only the given metric in overhead tests
+    #[clap(long, default_value = "all", possible_values = OverheadMetric::NAMES)]
+    overhead_metric: String,
+
+    /// Exclude the time of delivering the first n messages from throughput in overhead tests
+    #[clap(long, default_value = "0", value_name = "N")]
+    overhead_warmup: usize,
+
+    /// Save measurements of overhead tests to CSV file
+    #[clap(long)]
+    overhead_csv: Option<String>,
+
     /// Run model checking tests
     #[clap(long, short = 'c')]
     model_checking: bool,
+
+    /// Check model checking invariants only in every n-th explored state (and in final states)
+    #[clap(long, default_value = "1")]
+    mc_check_every: u32,
+
+    /// Continue model checking after an invariant violation until this many distinct violations are found
+    #[clap(long, default_value = "1")]
+    mc_max_violations: u32,
+
+    /// Stop each model checking search after this real time in seconds, such a test is inconclusive and not passed
+    #[clap(long)]
+    mc_time_limit: Option<f64>,
+
+    /// Search strategy used in model checking tests
+    #[clap(long, default_value = "Bfs", possible_values = MC_STRATEGIES)]
+    mc_strategy: String,
+
+    /// Order of trying enabled events in Bfs and Dfs model checking strategies
+    #[clap(long, default_value = "Interleaved", possible_values = EventOrder::NAMES)]
+    mc_event_order: String,
+
+    /// Number of random walks made by RandomWalk model checking strategy
+    #[clap(long, default_value = "100")]
+    mc_walks: u32,
+
+    /// Random seed of RandomWalk model checking strategy (the seed of tests by default)
+    #[clap(long)]
+    mc_walk_seed: Option<u64>,
+
+    /// Depth bound of the first iteration of Iddfs model checking strategy
+    #[clap(long, default_value = "1")]
+    mc_min_depth: u64,
+
+    /// Depth bound of the last iteration of Iddfs model checking strategy
+    #[clap(long, default_value = "20")]
+    mc_max_depth: u64,
+
+    /// Use partial-order reduction in model checking to skip redundant interleavings of independent events
+    #[clap(long)]
+    mc_por: bool,
+
+    /// Use symmetry reduction in model checking to skip states differing only by permutation of message texts
+    #[clap(long)]
+    mc_symmetry: bool,
+
+    /// Save the states explored by model checking in the test selected with --test to file in DOT format
+    #[clap(long, requires = "test")]
+    mc_graph: Option<String>,
+
+    /// Run model checking scenario from file in addition to the built-in ones
+    #[clap(long)]
+    mc_scenario: Option<String>,
+
+    /// Replay network decisions from schedule file instead of random ones
+    #[clap(long)]
+    schedule: Option<String>,
+
+    /// Save schedule of the first failed chaos monkey run to file
+    #[clap(long)]
+    save_schedule: Option<String>,
+
+    /// Directory with additional test scenarios to run
+    #[clap(long)]
+    hidden_tests: Option<String>,
+
+    /// Run the test selected with --test this many times and check that all runs have identical traces
+    #[clap(long, requires = "test")]
+    check_determinism: Option<u32>,
+
+    /// Save execution traces as JSON lines to directory, a file per built system
+    #[clap(long, conflicts_with = "check_determinism")]
+    trace_dir: Option<String>,
+
+    /// Compute the score with the rubric from file instead of the default one
+    #[clap(long)]
+    score_config: Option<String>,
+
+    /// Check the solution source in overhead tests with the patterns from file instead of the default ones
+    #[clap(long, value_name = "PATH")]
+    source_check: Option<String>,
+
+    /// Save the results of all tests with their durations and the score to file as JSON
+    #[clap(long, conflicts_with = "test")]
+    report: Option<String>,
+
+    /// Print features supported by the tests as JSON and exit
+    #[clap(long)]
+    capabilities: bool,
 }
 
 fn main() {
     let args = Args::parse();
+    if args.capabilities {
+        print_capabilities();
+        return;
+    }
     if args.debug {
+        // events are filtered by their text independently of the log level, since the trace has no process field
+        let proc_filter = (!args.debug_proc.is_empty()).then(|| {
+            let names: Vec<String> = args.debug_proc.iter().map(|name| regex::escape(name)).collect();
+            Regex::new(&format!(r"\b({})\b", names.join("|"))).unwrap()
+        });
         Builder::new()
             .filter(Some("anysystem"), LevelFilter::Debug)
-            .format(|buf, record| writeln!(buf, "{}", record.args()))
+            .format(move |buf, record| {
+                let text = record.args().to_string();
+                match &proc_filter {
+                    Some(filter) if !filter.is_match(&text) => Ok(()),
+                    _ => writeln!(buf, "{}", text),
+                }
+            })
             .init();
     }
     let guarantee = args.guarantee.as_deref();
 
+    if args.mc_min_depth > args.mc_max_depth {
+        eprintln!("--mc-min-depth should not exceed --mc-max-depth");
+        process::exit(1);
+    }
+
+    let hidden_tests = match args.hidden_tests.as_deref().map(load_hidden_tests).transpose() {
+        Ok(hidden_tests) => hidden_tests.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+
+    let score_config = match args.score_config.as_deref().map(load_score_config).transpose() {
+        Ok(score_config) => score_config.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+
+    let source_check_config = match args.source_check.as_deref().map(load_source_check_config).transpose() {
+        Ok(source_check_config) => source_check_config.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+    let forbidden_patterns: Vec<&str> = source_check_config.forbidden.iter().map(String::as_str).collect();
+    let required_patterns: Vec<&str> = source_check_config.required.iter().map(String::as_str).collect();
+
+    let filter = match args.filter.as_deref().map(Regex::new).transpose() {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("Bad --filter: {e}");
+            process::exit(1);
+        }
+    };
+
+    let process_kwargs: HashMap<String, String> = match args
+        .kwargs
+        .iter()
+        .map(|kwarg| {
+            let (key, value) = kwarg
+                .split_once('=')
+                .ok_or(format!("Bad --kwarg, expected KEY=VALUE: {kwarg}"))?;
+            Ok((key.to_string(), value.to_string()))
+        })
+        .collect::<Result<_, String>>()
+    {
+        Ok(process_kwargs) => process_kwargs,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+
+    let mc_scenario = match args.mc_scenario.as_deref().map(load_mc_scenario).transpose() {
+        Ok(mc_scenario) => mc_scenario,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+
+    // the traces of the runs are compared, so they are written to files
+    let trace_dir = env::temp_dir()
+        .join(format!("distsys-guarantees-{}", process::id()))
+        .to_string_lossy()
+        .to_string();
+
+    // a replayed schedule brings the hash seed of the run it was recorded from, as it affects the solution behavior
+    let hash_seed = match args.schedule.as_deref().map(Schedule::load).transpose() {
+        Ok(schedule) => schedule.and_then(|schedule| schedule.hash_seed).unwrap_or(args.seed),
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+
     append_to_python_path("../../anysystem/python".to_string());
-    env::set_var("PYTHONHASHSEED", args.seed.to_string());
+    env::set_var("PYTHONHASHSEED", hash_seed.to_string());
     let mut config = TestConfig {
         impl_path: &args.solution_path,
+        process_kwargs: &process_kwargs,
+        clock_rates: &[],
+        guarantee: "",
         sender_class: "",
         receiver_class: "",
         seed: args.seed,
         monkeys: args.monkeys,
+        // parallel chaos monkey runs would build the systems in arbitrary order
+        chaos_jobs: if args.check_determinism.is_some() {
+            1
+        } else {
+            args.chaos_jobs
+        },
+        chaos: ChaosParams::default(),
+        max_steps: args.max_steps,
+        settle: args.settle,
+        mc_check_every: args.mc_check_every,
+        mc_max_violations: args.mc_max_violations,
+        mc_time_limit: args.mc_time_limit,
+        mc_strategy: &args.mc_strategy,
+        mc_event_order: EventOrder::from_name(&args.mc_event_order),
+        mc_walks: args.mc_walks,
+        mc_walk_seed: args.mc_walk_seed,
+        mc_min_depth: args.mc_min_depth,
+        mc_max_depth: args.mc_max_depth,
+        mc_por: args.mc_por,
+        mc_symmetry: args.mc_symmetry,
+        mc_graph: args.mc_graph.as_deref(),
+        prewarm: args.prewarm,
+        overhead_metric: OverheadMetric::from_name(&args.overhead_metric),
+        overhead_warmup: args.overhead_warmup,
+        source_check: SourceCheck {
+            forbidden: &forbidden_patterns,
+            required: &required_patterns,
+        },
+        duplicate_local_sends: false,
         reliable: false,
         once: false,
         ordered: false,
+        schedule: args.schedule.as_deref(),
+        save_schedule: args.save_schedule.as_deref(),
+        hidden_test: None,
+        mc_scenario: None,
+        trace_dir: match args.check_determinism {
+            Some(_) => Some(trace_dir.as_str()),
+            None => args.trace_dir.as_deref(),
+        },
     };
     let mut tests = TestSuite::new();
 
     // At most once
     if guarantee.is_none() || guarantee == Some("AMO") {
+        config.guarantee = "AMO";
         config.sender_class = "AtMostOnceSender";
         config.receiver_class = "AtMostOnceReceiver";
         config.once = true;
@@ -86,15 +379,53 @@ fn main() {
         config.reliable = true;
         tests.add("[AT MOST ONCE] NORMAL", test_normal, config);
         tests.add("[AT MOST ONCE] NORMAL NON-UNIQUE", test_normal_non_unique, config);
+        tests.add("[AT MOST ONCE] SPECIAL CHARACTERS", test_special_characters, config);
         tests.add("[AT MOST ONCE] DELAYED", test_delayed, config);
+        tests.add("[AT MOST ONCE] OVERTAKEN MESSAGE", test_overtaken_message, config);
+        tests.add("[AT MOST ONCE] BURST", test_burst, config);
+        tests.add("[AT MOST ONCE] LATENCY SPIKE", test_latency_spike, config);
         tests.add("[AT MOST ONCE] DUPLICATED", test_duplicated, config);
+        tests.add("[AT MOST ONCE] DUPLICATED NTH", test_duplicated_nth, config);
         tests.add("[AT MOST ONCE] DELAYED+DUPLICATED", test_delayed_duplicated, config);
+        let mut dupl_config = config;
+        dupl_config.duplicate_local_sends = true;
+        tests.add(
+            "[AT MOST ONCE] DUPLICATE LOCAL SENDS",
+            test_delayed_duplicated,
+            dupl_config,
+        );
         // with drops is not reliable
         config.reliable = false;
         tests.add("[AT MOST ONCE] DROPPED", test_dropped, config);
+        tests.add(
+            "[AT MOST ONCE] DROPPED AFTER FIRST ACK",
+            test_dropped_after_first_ack,
+            config,
+        );
+        tests.add("[AT MOST ONCE] DROPPED NTH", test_dropped_nth, config);
+        tests.add("[AT MOST ONCE] DROPPED MATCHING", test_dropped_matching, config);
+        tests.add("[AT MOST ONCE] DROPPED BUDGET", test_dropped_budget, config);
+        tests.add("[AT MOST ONCE] PARTITION", test_partition, config);
+        tests.add("[AT MOST ONCE] DROP OUTAGE", test_drop_outage, config);
+        tests.add("[AT MOST ONCE] HEAVY-TAILED DELAYS", test_heavy_tailed_delays, config);
+        tests.add("[AT MOST ONCE] ASYMMETRIC LINKS", test_asymmetric_links, config);
+        tests.add("[AT MOST ONCE] ACK LOSS", test_ack_loss, config);
+        tests.add("[AT MOST ONCE] CLOCK SKEW", test_clock_skew, config);
+        tests.add("[AT MOST ONCE] DELIVERY RECEIPTS", test_delivery_receipts, config);
+        tests.add("[AT MOST ONCE] SLOW CONSUMER", test_slow_consumer, config);
+        tests.add("[AT MOST ONCE] RECEIVER RESTART", test_receiver_restart, config);
+        tests.add(
+            "[AT MOST ONCE] DETERMINISTIC STARTUP",
+            test_deterministic_startup,
+            config,
+        );
         if args.monkeys > 0 {
             tests.add("[AT MOST ONCE] CHAOS MONKEY", test_chaos_monkey, config);
         }
+        if args.schedule.is_some() {
+            tests.add("[AT MOST ONCE] SCHEDULE", test_schedule, config);
+        }
+        add_hidden_tests(&mut tests, config, &hidden_tests, "AT MOST ONCE");
         if args.overhead {
             config.reliable = true;
             tests.add(
@@ -102,6 +433,13 @@ fn main() {
                 |x| test_overhead(x, "AMO", false),
                 config,
             );
+            tests.add("[AT MOST ONCE] OVERHEAD MEMORY PRESSURE", test_memory_pressure, config);
+            tests.add(
+                "[AT MOST ONCE] OVERHEAD LIMITED BANDWIDTH",
+                test_limited_bandwidth,
+                config,
+            );
+            tests.add("[AT MOST ONCE] OVERHEAD LARGE MESSAGES", test_large_messages, config);
             config.reliable = false;
             tests.add(
                 "[AT MOST ONCE] OVERHEAD FAULTY",
@@ -121,30 +459,93 @@ fn main() {
                 test_mc_unstable_network,
                 config,
             );
+            tests.add(
+                "[AT MOST ONCE] MODEL CHECKING FROM SNAPSHOT",
+                test_mc_from_snapshot,
+                config,
+            );
         }
+        add_mc_scenario(&mut tests, config, mc_scenario.as_ref(), "AT MOST ONCE");
     }
 
     // At least once
     if guarantee.is_none() || guarantee == Some("ALO") {
+        config.guarantee = "ALO";
         config.sender_class = "AtLeastOnceSender";
         config.receiver_class = "AtLeastOnceReceiver";
         config.reliable = true;
         config.once = false;
         tests.add("[AT LEAST ONCE] NORMAL", test_normal, config);
         tests.add("[AT LEAST ONCE] NORMAL NON-UNIQUE", test_normal_non_unique, config);
+        tests.add("[AT LEAST ONCE] SPECIAL CHARACTERS", test_special_characters, config);
         tests.add("[AT LEAST ONCE] DELAYED", test_delayed, config);
+        tests.add("[AT LEAST ONCE] OVERTAKEN MESSAGE", test_overtaken_message, config);
+        tests.add("[AT LEAST ONCE] BURST", test_burst, config);
+        tests.add("[AT LEAST ONCE] LATENCY SPIKE", test_latency_spike, config);
         tests.add("[AT LEAST ONCE] DUPLICATED", test_duplicated, config);
+        tests.add("[AT LEAST ONCE] DUPLICATED NTH", test_duplicated_nth, config);
+        tests.add("[AT LEAST ONCE] BROADCAST", test_broadcast, config);
         tests.add("[AT LEAST ONCE] DELAYED+DUPLICATED", test_delayed_duplicated, config);
+        let mut dupl_config = config;
+        dupl_config.duplicate_local_sends = true;
+        tests.add(
+            "[AT LEAST ONCE] DUPLICATE LOCAL SENDS",
+            test_delayed_duplicated,
+            dupl_config,
+        );
         tests.add("[AT LEAST ONCE] DROPPED", test_dropped, config);
+        tests.add(
+            "[AT LEAST ONCE] DROPPED AFTER FIRST ACK",
+            test_dropped_after_first_ack,
+            config,
+        );
+        tests.add("[AT LEAST ONCE] DROPPED MATCHING", test_dropped_matching, config);
+        tests.add("[AT LEAST ONCE] DROPPED BUDGET", test_dropped_budget, config);
+        tests.add("[AT LEAST ONCE] PARTITION", test_partition, config);
+        tests.add("[AT LEAST ONCE] DROP OUTAGE", test_drop_outage, config);
+        tests.add("[AT LEAST ONCE] HEAVY-TAILED DELAYS", test_heavy_tailed_delays, config);
+        tests.add("[AT LEAST ONCE] ASYMMETRIC LINKS", test_asymmetric_links, config);
+        tests.add("[AT LEAST ONCE] ACK LOSS", test_ack_loss, config);
+        tests.add("[AT LEAST ONCE] CLOCK SKEW", test_clock_skew, config);
+        tests.add("[AT LEAST ONCE] DELIVERY RECEIPTS", test_delivery_receipts, config);
+        tests.add("[AT LEAST ONCE] SLOW CONSUMER", test_slow_consumer, config);
+        tests.add("[AT LEAST ONCE] RECEIVER RESTART", test_receiver_restart, config);
+        tests.add(
+            "[AT LEAST ONCE] DETERMINISTIC STARTUP",
+            test_deterministic_startup,
+            config,
+        );
         if args.monkeys > 0 {
             tests.add("[AT LEAST ONCE] CHAOS MONKEY", test_chaos_monkey, config);
         }
+        if args.schedule.is_some() {
+            tests.add("[AT LEAST ONCE] SCHEDULE", test_schedule, config);
+        }
+        add_hidden_tests(&mut tests, config, &hidden_tests, "AT LEAST ONCE");
         if args.overhead {
             tests.add(
                 "[AT LEAST ONCE] OVERHEAD NORMAL",
                 |x| test_overhead(x, "ALO", false),
                 config,
             );
+            tests.add("[AT LEAST ONCE] OVERHEAD MEMORY PRESSURE", test_memory_pressure, config);
+            tests.add(
+                "[AT LEAST ONCE] OVERHEAD LIMITED BANDWIDTH",
+                test_limited_bandwidth,
+                config,
+            );
+            tests.add("[AT LEAST ONCE] OVERHEAD LARGE MESSAGES", test_large_messages, config);
+            tests.add(
+                "[AT LEAST ONCE] OVERHEAD SELECTIVE RETRANSMIT",
+                test_selective_retransmit,
+                config,
+            );
+            tests.add("[AT LEAST ONCE] OVERHEAD PENDING TIMERS", test_pending_timers, config);
+            tests.add(
+                "[AT LEAST ONCE] OVERHEAD IN-FLIGHT COPIES",
+                test_in_flight_copies,
+                config,
+            );
             tests.add(
                 "[AT LEAST ONCE] OVERHEAD FAULTY",
                 |x| test_overhead(x, "ALO", true),
@@ -163,30 +564,93 @@ fn main() {
                 test_mc_unstable_network,
                 config,
             );
+            tests.add(
+                "[AT LEAST ONCE] MODEL CHECKING FROM SNAPSHOT",
+                test_mc_from_snapshot,
+                config,
+            );
         }
+        add_mc_scenario(&mut tests, config, mc_scenario.as_ref(), "AT LEAST ONCE");
     }
 
     // Exactly once
     if guarantee.is_none() || guarantee == Some("EO") {
+        config.guarantee = "EO";
         config.sender_class = "ExactlyOnceSender";
         config.receiver_class = "ExactlyOnceReceiver";
         config.reliable = true;
         config.once = true;
         tests.add("[EXACTLY ONCE] NORMAL", test_normal, config);
         tests.add("[EXACTLY ONCE] NORMAL NON-UNIQUE", test_normal_non_unique, config);
+        tests.add("[EXACTLY ONCE] SPECIAL CHARACTERS", test_special_characters, config);
         tests.add("[EXACTLY ONCE] DELAYED", test_delayed, config);
+        tests.add("[EXACTLY ONCE] OVERTAKEN MESSAGE", test_overtaken_message, config);
+        tests.add("[EXACTLY ONCE] BURST", test_burst, config);
+        tests.add("[EXACTLY ONCE] LATENCY SPIKE", test_latency_spike, config);
         tests.add("[EXACTLY ONCE] DUPLICATED", test_duplicated, config);
+        tests.add("[EXACTLY ONCE] DUPLICATED NTH", test_duplicated_nth, config);
+        tests.add("[EXACTLY ONCE] BROADCAST", test_broadcast, config);
         tests.add("[EXACTLY ONCE] DELAYED+DUPLICATED", test_delayed_duplicated, config);
+        let mut dupl_config = config;
+        dupl_config.duplicate_local_sends = true;
+        tests.add(
+            "[EXACTLY ONCE] DUPLICATE LOCAL SENDS",
+            test_delayed_duplicated,
+            dupl_config,
+        );
         tests.add("[EXACTLY ONCE] DROPPED", test_dropped, config);
+        tests.add(
+            "[EXACTLY ONCE] DROPPED AFTER FIRST ACK",
+            test_dropped_after_first_ack,
+            config,
+        );
+        tests.add("[EXACTLY ONCE] DROPPED MATCHING", test_dropped_matching, config);
+        tests.add("[EXACTLY ONCE] DROPPED BUDGET", test_dropped_budget, config);
+        tests.add("[EXACTLY ONCE] PARTITION", test_partition, config);
+        tests.add("[EXACTLY ONCE] DROP OUTAGE", test_drop_outage, config);
+        tests.add("[EXACTLY ONCE] HEAVY-TAILED DELAYS", test_heavy_tailed_delays, config);
+        tests.add("[EXACTLY ONCE] ASYMMETRIC LINKS", test_asymmetric_links, config);
+        tests.add("[EXACTLY ONCE] ACK LOSS", test_ack_loss, config);
+        tests.add("[EXACTLY ONCE] CLOCK SKEW", test_clock_skew, config);
+        tests.add("[EXACTLY ONCE] DELIVERY RECEIPTS", test_delivery_receipts, config);
+        tests.add("[EXACTLY ONCE] SLOW CONSUMER", test_slow_consumer, config);
+        tests.add("[EXACTLY ONCE] RECEIVER RESTART", test_receiver_restart, config);
+        tests.add(
+            "[EXACTLY ONCE] DETERMINISTIC STARTUP",
+            test_deterministic_startup,
+            config,
+        );
         if args.monkeys > 0 {
             tests.add("[EXACTLY ONCE] CHAOS MONKEY", test_chaos_monkey, config);
         }
+        if args.schedule.is_some() {
+            tests.add("[EXACTLY ONCE] SCHEDULE", test_schedule, config);
+        }
+        add_hidden_tests(&mut tests, config, &hidden_tests, "EXACTLY ONCE");
         if args.overhead {
             tests.add(
                 "[EXACTLY ONCE] OVERHEAD NORMAL",
                 |x| test_overhead(x, "EO", false),
                 config,
             );
+            tests.add("[EXACTLY ONCE] OVERHEAD MEMORY PRESSURE", test_memory_pressure, config);
+            tests.add(
+                "[EXACTLY ONCE] OVERHEAD LIMITED BANDWIDTH",
+                test_limited_bandwidth,
+                config,
+            );
+            tests.add("[EXACTLY ONCE] OVERHEAD LARGE MESSAGES", test_large_messages, config);
+            tests.add(
+                "[EXACTLY ONCE] OVERHEAD SELECTIVE RETRANSMIT",
+                test_selective_retransmit,
+                config,
+            );
+            tests.add("[EXACTLY ONCE] OVERHEAD PENDING TIMERS", test_pending_timers, config);
+            tests.add(
+                "[EXACTLY ONCE] OVERHEAD IN-FLIGHT COPIES",
+                test_in_flight_copies,
+                config,
+            );
             tests.add(
                 "[EXACTLY ONCE] OVERHEAD FAULTY",
                 |x| test_overhead(x, "EO", true),
@@ -205,11 +669,18 @@ fn main() {
                 test_mc_unstable_network,
                 config,
             );
+            tests.add(
+                "[EXACTLY ONCE] MODEL CHECKING FROM SNAPSHOT",
+                test_mc_from_snapshot,
+                config,
+            );
         }
+        add_mc_scenario(&mut tests, config, mc_scenario.as_ref(), "EXACTLY ONCE");
     }
 
     // EXACTLY ONCE ORDERED
     if guarantee.is_none() || guarantee == Some("EOO") {
+        config.guarantee = "EOO";
         config.sender_class = "ExactlyOnceOrderedSender";
         config.receiver_class = "ExactlyOnceOrderedReceiver";
         config.reliable = true;
@@ -221,23 +692,114 @@ fn main() {
             test_normal_non_unique,
             config,
         );
+        tests.add(
+            "[EXACTLY ONCE ORDERED] SPECIAL CHARACTERS",
+            test_special_characters,
+            config,
+        );
         tests.add("[EXACTLY ONCE ORDERED] DELAYED", test_delayed, config);
+        tests.add(
+            "[EXACTLY ONCE ORDERED] OVERTAKEN MESSAGE",
+            test_overtaken_message,
+            config,
+        );
+        tests.add("[EXACTLY ONCE ORDERED] BURST", test_burst, config);
+        tests.add("[EXACTLY ONCE ORDERED] LATENCY SPIKE", test_latency_spike, config);
         tests.add("[EXACTLY ONCE ORDERED] DUPLICATED", test_duplicated, config);
+        tests.add("[EXACTLY ONCE ORDERED] DUPLICATED NTH", test_duplicated_nth, config);
+        tests.add("[EXACTLY ONCE ORDERED] BROADCAST", test_broadcast, config);
         tests.add(
             "[EXACTLY ONCE ORDERED] DELAYED+DUPLICATED",
             test_delayed_duplicated,
             config,
         );
+        let mut dupl_config = config;
+        dupl_config.duplicate_local_sends = true;
+        tests.add(
+            "[EXACTLY ONCE ORDERED] DUPLICATE LOCAL SENDS",
+            test_delayed_duplicated,
+            dupl_config,
+        );
+        tests.add("[EXACTLY ONCE ORDERED] CAUSAL ORDER", test_causal_order, config);
         tests.add("[EXACTLY ONCE ORDERED] DROPPED", test_dropped, config);
+        tests.add(
+            "[EXACTLY ONCE ORDERED] DROPPED AFTER FIRST ACK",
+            test_dropped_after_first_ack,
+            config,
+        );
+        tests.add("[EXACTLY ONCE ORDERED] DROPPED MATCHING", test_dropped_matching, config);
+        tests.add("[EXACTLY ONCE ORDERED] DROPPED BUDGET", test_dropped_budget, config);
+        tests.add("[EXACTLY ONCE ORDERED] PARTITION", test_partition, config);
+        tests.add("[EXACTLY ONCE ORDERED] DROP OUTAGE", test_drop_outage, config);
+        tests.add(
+            "[EXACTLY ONCE ORDERED] HEAVY-TAILED DELAYS",
+            test_heavy_tailed_delays,
+            config,
+        );
+        tests.add("[EXACTLY ONCE ORDERED] ASYMMETRIC LINKS", test_asymmetric_links, config);
+        tests.add("[EXACTLY ONCE ORDERED] ACK LOSS", test_ack_loss, config);
+        tests.add("[EXACTLY ONCE ORDERED] CLOCK SKEW", test_clock_skew, config);
+        tests.add("[EXACTLY ONCE ORDERED] FIFO LINK", test_fifo_link, config);
+        tests.add(
+            "[EXACTLY ONCE ORDERED] DELIVERY RECEIPTS",
+            test_delivery_receipts,
+            config,
+        );
+        tests.add("[EXACTLY ONCE ORDERED] SLOW CONSUMER", test_slow_consumer, config);
+        tests.add("[EXACTLY ONCE ORDERED] RECEIVER RESTART", test_receiver_restart, config);
+        tests.add(
+            "[EXACTLY ONCE ORDERED] DETERMINISTIC STARTUP",
+            test_deterministic_startup,
+            config,
+        );
         if args.monkeys > 0 {
             tests.add("[EXACTLY ONCE ORDERED] CHAOS MONKEY", test_chaos_monkey, config);
         }
+        if args.schedule.is_some() {
+            tests.add("[EXACTLY ONCE ORDERED] SCHEDULE", test_schedule, config);
+        }
+        add_hidden_tests(&mut tests, config, &hidden_tests, "EXACTLY ONCE ORDERED");
         if args.overhead {
             tests.add(
                 "[EXACTLY ONCE ORDERED] OVERHEAD NORMAL",
                 |x| test_overhead(x, "EOO", false),
                 config,
             );
+            tests.add(
+                "[EXACTLY ONCE ORDERED] OVERHEAD MEMORY PRESSURE",
+                test_memory_pressure,
+                config,
+            );
+            tests.add(
+                "[EXACTLY ONCE ORDERED] OVERHEAD LIMITED BANDWIDTH",
+                test_limited_bandwidth,
+                config,
+            );
+            tests.add(
+                "[EXACTLY ONCE ORDERED] OVERHEAD LARGE MESSAGES",
+                test_large_messages,
+                config,
+            );
+            tests.add(
+                "[EXACTLY ONCE ORDERED] OVERHEAD SELECTIVE RETRANSMIT",
+                test_selective_retransmit,
+                config,
+            );
+            tests.add(
+                "[EXACTLY ONCE ORDERED] OVERHEAD PENDING TIMERS",
+                test_pending_timers,
+                config,
+            );
+            tests.add(
+                "[EXACTLY ONCE ORDERED] OVERHEAD IN-FLIGHT COPIES",
+                test_in_flight_copies,
+                config,
+            );
+            tests.add(
+                "[EXACTLY ONCE ORDERED] OVERHEAD HEAD-OF-LINE STALL",
+                test_head_of_line_stall,
+                config,
+            );
             tests.add(
                 "[EXACTLY ONCE ORDERED] OVERHEAD FAULTY",
                 |x| test_overhead(x, "EOO", true),
@@ -260,25 +822,184 @@ fn main() {
                 test_mc_unstable_network,
                 config,
             );
+            tests.add(
+                "[EXACTLY ONCE ORDERED] MODEL CHECKING FROM SNAPSHOT",
+                test_mc_from_snapshot,
+                config,
+            );
         }
+        add_mc_scenario(&mut tests, config, mc_scenario.as_ref(), "EXACTLY ONCE ORDERED");
+    }
+
+    // the suite runs each test in a child process when the tests are limited in time
+    if let Ok(test) = env::var(TEST_PROCESS_VAR) {
+        tests.run_child_test(&test);
+        return;
+    }
+    if let Some(timeout) = args.test_timeout {
+        tests.set_timeout(Duration::from_secs_f64(timeout));
     }
 
-    if args.test.is_none() {
-        let (_, results) = tests.run();
-        let score = score(results);
-        println!("SCORE: {score}\n");
+    if let (Some(test), Some(runs)) = (&args.test, args.check_determinism) {
+        let res = check_determinism(&mut tests, test, runs, &trace_dir);
+        let _ = fs::remove_dir_all(&trace_dir);
+        match res {
+            Ok(()) => println!("Test {test} is deterministic: {runs} runs have identical traces"),
+            Err(e) => {
+                eprintln!("NONDETERMINISM: {e}");
+                process::exit(1);
+            }
+        }
+    } else if let Some(test) = args.test {
+        tests.run_test(&test);
     } else {
-        tests.run_test(&args.test.unwrap());
+        if let (Some(filter), true) = (&filter, args.report.is_some() || args.jobs > 1) {
+            tests.set_filter(filter.clone());
+        }
+        let (results, report) = match args.report {
+            Some(_) => {
+                let (results, report) = tests.run_with_report(args.jobs);
+                (results, Some(report))
+            }
+            None if args.jobs > 1 => (tests.run_parallel(args.jobs).1, None),
+            None => match &filter {
+                Some(filter) => (tests.run_matching(filter).1, None),
+                None => (tests.run().1, None),
+            },
+        };
+        // the summary compares all metrics, so it is not printed for a single one
+        let overhead_rows = take_overhead_rows();
+        if args.overhead && config.overhead_metric == OverheadMetric::All {
+            print_overhead_summary(&overhead_rows);
+        }
+        if let Some(path) = &args.overhead_csv {
+            if let Err(e) = write_overhead_csv(path, &overhead_rows) {
+                eprintln!("{e}");
+            }
+        }
+        let inconclusive: Vec<&str> = results
+            .iter()
+            .filter(|(_, result)| result.as_ref().is_err_and(|e| e.starts_with(INCONCLUSIVE)))
+            .map(|(test, _)| test.as_str())
+            .collect();
+        if !inconclusive.is_empty() {
+            println!("INCONCLUSIVE (counted as failed): {}", inconclusive.join(", "));
+        }
+        let inconclusive: Vec<String> = inconclusive.into_iter().map(String::from).collect();
+        // the score of a part of the tests is meaningless
+        let score = filter.is_none().then(|| score(results, &hidden_tests, &score_config));
+        if let Some(score) = score {
+            println!("SCORE: {score}\n");
+        }
+        if let (Some(path), Some(report)) = (&args.report, report) {
+            let report = json!({
+                "tests": report,
+                "inconclusive": inconclusive,
+                "score": score,
+            });
+            if let Err(e) = fs::write(path, serde_json::to_string_pretty(&report).unwrap()) {
+                eprintln!("Cannot write report to {path}: {e}");
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Runs the test several times with the same seed and checks that every run produces the same traces
+/// (delivered messages, network events, timers) as the first one.
+fn check_determinism(tests: &mut TestSuite<TestConfig>, test: &str, runs: u32, trace_dir: &str) -> Result<(), String> {
+    let mut first_traces = None;
+    for run in 1..=runs {
+        println!("Determinism check: run {run} of {runs}");
+        tests.run_test(test);
+        let traces = take_traces(trace_dir)?;
+        let Some(first_traces) = &first_traces else {
+            if traces.is_empty() {
+                return Err(format!("Test {test} is not found or does not build a system"));
+            }
+            first_traces = Some(traces);
+            continue;
+        };
+        if let Some(divergence) = trace_divergence(first_traces, &traces) {
+            return Err(format!("run {run} diverged from run 1 {divergence}"));
+        }
+    }
+    Ok(())
+}
+
+type Traces = Vec<(String, Vec<String>)>;
+
+/// Describes where the traces diverge if they differ.
+fn trace_divergence(expected: &Traces, actual: &Traces) -> Option<String> {
+    if expected.len() != actual.len() {
+        return Some(format!(
+            "in the number of built systems: {} instead of {}",
+            actual.len(),
+            expected.len()
+        ));
+    }
+    for ((file, expected), (_, actual)) in expected.iter().zip(actual) {
+        let len = expected.len().max(actual.len());
+        if let Some(i) = (0..len).find(|&i| expected.get(i) != actual.get(i)) {
+            let missing = "<end of trace>".to_string();
+            return Some(format!(
+                "in {file} at event {}:\n  expected: {}\n  actual:   {}",
+                i + 1,
+                expected.get(i).unwrap_or(&missing),
+                actual.get(i).unwrap_or(&missing)
+            ));
+        }
+    }
+    None
+}
+
+fn add_hidden_tests<'a>(
+    tests: &mut TestSuite<TestConfig<'a>>,
+    mut config: TestConfig<'a>,
+    hidden_tests: &'a [HiddenTest],
+    guarantee_name: &str,
+) {
+    for hidden_test in hidden_tests.iter().filter(|t| t.runs_for(config.guarantee)) {
+        config.hidden_test = Some(hidden_test);
+        tests.add(
+            &format!("[{guarantee_name}] HIDDEN {}", hidden_test.name),
+            test_hidden,
+            config,
+        );
+    }
+}
+
+fn add_mc_scenario<'a>(
+    tests: &mut TestSuite<TestConfig<'a>>,
+    mut config: TestConfig<'a>,
+    mc_scenario: Option<&'a McScenario>,
+    guarantee_name: &str,
+) {
+    if let Some(mc_scenario) = mc_scenario.filter(|s| s.runs_for(config.guarantee)) {
+        config.mc_scenario = Some(mc_scenario);
+        tests.add(
+            &format!("[{guarantee_name}] MODEL CHECKING SCENARIO {}", mc_scenario.name),
+            test_mc_scenario,
+            config,
+        );
     }
 }
 
-fn score(results: BTreeMap<String, TestResult>) -> f32 {
-    let guarantees = HashSet::from(["AT MOST ONCE", "AT LEAST ONCE", "EXACTLY ONCE", "EXACTLY ONCE ORDERED"]);
+fn score(results: BTreeMap<String, TestResult>, hidden_tests: &[HiddenTest], score_config: &ScoreConfig) -> f32 {
     let mut failed_guarantees: HashSet<&str> = HashSet::new();
     let mut failed_overheads: HashSet<&str> = HashSet::new();
+    let mut hidden_penalty = 0.;
     for (test, result) in results {
         if result.is_err() {
-            for guarantee in guarantees.iter() {
+            // hidden tests are weighted separately from the guarantees
+            if let Some(hidden_test) = hidden_tests
+                .iter()
+                .find(|t| test.ends_with(&format!("] HIDDEN {}", t.name)))
+            {
+                hidden_penalty += hidden_test.weight;
+                continue;
+            }
+            for guarantee in score_config.guarantee_weights.keys() {
                 if test.contains(format!("[{guarantee}]").as_str()) {
                     if test.contains("OVERHEAD") {
                         failed_overheads.insert(guarantee);
@@ -289,7 +1010,55 @@ fn score(results: BTreeMap<String, TestResult>) -> f32 {
             }
         }
     }
-    9. - failed_guarantees.len() as f32 * 2. - f32::from(!failed_overheads.is_empty())
+    let guarantee_penalty: f32 = failed_guarantees
+        .iter()
+        .map(|guarantee| score_config.guarantee_weights[*guarantee])
+        .sum();
+    let overhead_penalty = if failed_overheads.is_empty() {
+        0.
+    } else {
+        score_config.overhead_penalty
+    };
+    let score = score_config.max_score - guarantee_penalty - overhead_penalty - hidden_penalty;
+    score.max(0.)
+}
+
+/// Output formats besides the text printed to stdout and the options writing them.
+const OUTPUT_FORMATS: [(&str, &str); 4] = [
+    ("json", "report"),
+    ("csv", "overhead-csv"),
+    ("jsonl", "trace-dir"),
+    ("dot", "mc-graph"),
+];
+
+/// Prints the features supported by the tests, so that the tools running them can adapt to the version.
+fn print_capabilities() {
+    let options: Vec<String> = Args::command()
+        .get_arguments()
+        .filter_map(|arg| arg.get_long())
+        .filter(|name| !["help", "version"].contains(name))
+        .map(|name| format!("--{name}"))
+        .collect();
+    // only the formats whose options are present are reported
+    let output_formats: Vec<&str> = ["text"]
+        .into_iter()
+        .chain(
+            OUTPUT_FORMATS
+                .iter()
+                .filter(|(_, option)| options.contains(&format!("--{option}")))
+                .map(|(format, _)| *format),
+        )
+        .collect();
+    let capabilities = json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "guarantees": GUARANTEES,
+        "mc_strategies": MC_STRATEGIES,
+        "network": NETWORK_KEYS,
+        "overhead_metrics": OverheadMetric::NAMES,
+        "output_formats": output_formats,
+        "options": options,
+    });
+    println!("{}", serde_json::to_string_pretty(&capabilities).unwrap());
 }
 
 fn append_to_python_path(entry: String) {
@@ -0,0 +1,249 @@
+use std::fs;
+use std::time::Duration;
+
+use anysystem::logger::LogEntry;
+use anysystem::mc::{GoalFn, InvariantFn, PruneFn};
+
+use crate::overhead::GUARANTEES;
+use crate::predicates::{goals, invariants, prunes};
+
+/// Model checking scenario loaded from a file passed with `--mc-scenario` instead of being defined in the code.
+///
+/// The format is the same as for hidden tests (see [`HiddenTest`](crate::hidden::HiddenTest)),
+/// with the predicates of the search referring to the constructors from [`predicates`](crate::predicates) by name:
+///
+/// ```text
+/// # a single drop and duplication, sender may retransmit once
+/// name = SHORT UNSTABLE
+/// guarantees = ALO EO EOO
+/// messages = 2
+/// drop_rate = 0.1
+/// dupl_rate = 0.1
+/// goal = got_messages 2 & no_events
+/// prune = events_limit dropped 1
+/// prune = events_limit duplicated 1
+/// prune = events_limit timer_fired 1
+/// invariant = state_depth 20
+/// ```
+///
+/// The search stops in a state matching any of the `goal` lines, each being a conjunction of goals joined with `&`
+/// (`no_events` by default). A state is pruned if it matches any of the `prune` lines, which are conjunctions
+/// of prunes as well. A goal or prune is negated with the `not` prefix, e.g. `prune = state_depth 5 & not events_limit
+/// dropped 0` explores only the branches with a dropped message beyond depth 5. The invariants of the guarantee
+/// are always checked in addition to the `invariant` lines. The supported predicates are:
+///
+/// - goals: `got_messages N` (delivered by receiver), `no_events`, `depth_reached N`, `events EVENT N`,
+///   `state_contains PROC TEXT` (the state of `sender` or `receiver` contains the text without spaces);
+/// - prunes: `state_depth N`, `sent_messages_limit N`, `events_limit EVENT N`, `deliveries_limit N`
+///   (same as `events_limit local_sent N`). The states of the model checker carry no simulated time,
+///   so there is no `time_limit` prune, the branches are bounded by depth with `state_depth`;
+/// - invariants: `state_depth N`, `time_limit SECONDS`, `max_process_size PROC BYTES` (`PROC` is `sender` or `receiver`);
+///
+/// where `EVENT` is one of [`EVENTS`].
+#[derive(Clone, Debug)]
+pub struct McScenario {
+    pub name: String,
+    pub guarantees: Vec<String>,
+    pub message_count: usize,
+    pub drop_rate: f64,
+    pub dupl_rate: f64,
+    pub goals: Vec<String>,
+    pub prunes: Vec<String>,
+    pub invariants: Vec<String>,
+}
+
+/// Names of the model checker events that can be counted by the predicates.
+pub const EVENTS: [&str; 7] = [
+    "sent",
+    "received",
+    "dropped",
+    "duplicated",
+    "timer_set",
+    "timer_fired",
+    "local_sent",
+];
+
+type EventPredicate = fn(&LogEntry) -> bool;
+
+fn event_predicate(name: &str) -> Result<EventPredicate, String> {
+    Ok(match name {
+        "sent" => LogEntry::is_mc_message_sent,
+        "received" => LogEntry::is_mc_message_received,
+        "dropped" => LogEntry::is_mc_message_dropped,
+        "duplicated" => LogEntry::is_mc_message_duplicated,
+        "timer_set" => LogEntry::is_mc_timer_set,
+        "timer_fired" => LogEntry::is_mc_timer_fired,
+        "local_sent" => LogEntry::is_mc_local_message_sent,
+        _ => return Err(format!("Unknown event {name}, expected one of {EVENTS:?}")),
+    })
+}
+
+/// Splits a predicate into its name and arguments.
+fn split_predicate(text: &str) -> (&str, Vec<&str>) {
+    let mut parts = text.split_whitespace();
+    (parts.next().unwrap_or_default(), parts.collect())
+}
+
+fn parse_arg<T: std::str::FromStr>(text: &str, arg: &str) -> Result<T, String> {
+    arg.parse().map_err(|_| format!("Bad argument of {text}: {arg}"))
+}
+
+fn build_single_goal(text: &str) -> Result<GoalFn, String> {
+    if let Some(goal) = text.strip_prefix("not ") {
+        return Ok(goals::not(build_single_goal(goal.trim())?));
+    }
+    let (name, args) = split_predicate(text);
+    match (name, args.as_slice()) {
+        ("got_messages", [n]) => Ok(goals::got_n_local_messages(
+            "receiver-node",
+            "receiver",
+            parse_arg(text, n)?,
+        )),
+        ("no_events", []) => Ok(goals::no_events()),
+        ("depth_reached", [depth]) => Ok(goals::depth_reached(parse_arg(text, depth)?)),
+        ("events", [event, n]) => Ok(goals::event_happened_n_times_current_run(
+            event_predicate(event)?,
+            parse_arg(text, n)?,
+        )),
+        ("state_contains", [proc @ ("sender" | "receiver"), pattern]) => {
+            let pattern = pattern.to_string();
+            Ok(goals::process_state_matches(
+                format!("{proc}-node"),
+                proc.to_string(),
+                move |state| state.contains(&pattern),
+            ))
+        }
+        _ => Err(format!("Unknown goal: {text}")),
+    }
+}
+
+/// Builds a goal from a line of the scenario: a conjunction of goals joined with `&`.
+pub fn build_goal(text: &str) -> Result<GoalFn, String> {
+    let goals = text
+        .split('&')
+        .map(|goal| build_single_goal(goal.trim()))
+        .collect::<Result<_, _>>()?;
+    Ok(goals::all_goals(goals))
+}
+
+fn build_single_prune(text: &str) -> Result<PruneFn, String> {
+    if let Some(prune) = text.strip_prefix("not ") {
+        return Ok(prunes::not(build_single_prune(prune.trim())?));
+    }
+    let (name, args) = split_predicate(text);
+    match (name, args.as_slice()) {
+        ("state_depth", [depth]) => Ok(prunes::state_depth(parse_arg(text, depth)?)),
+        ("sent_messages_limit", [n]) => Ok(prunes::sent_messages_limit(parse_arg(text, n)?)),
+        ("events_limit", [event, n]) => Ok(prunes::events_limit(event_predicate(event)?, parse_arg(text, n)?)),
+        ("deliveries_limit", [n]) => Ok(prunes::deliveries_limit(parse_arg(text, n)?)),
+        ("time_limit", _) => Err(format!(
+            "Unknown prune: {text} (model checking states carry no simulated time, use state_depth)"
+        )),
+        _ => Err(format!("Unknown prune: {text}")),
+    }
+}
+
+/// Builds a prune from a line of the scenario: a conjunction of prunes joined with `&`.
+pub fn build_prune(text: &str) -> Result<PruneFn, String> {
+    let prunes = text
+        .split('&')
+        .map(|prune| build_single_prune(prune.trim()))
+        .collect::<Result<_, _>>()?;
+    Ok(prunes::all_prune(prunes))
+}
+
+pub fn build_invariant(text: &str) -> Result<InvariantFn, String> {
+    let (name, args) = split_predicate(text);
+    match (name, args.as_slice()) {
+        ("state_depth", [depth]) => Ok(invariants::state_depth(parse_arg(text, depth)?)),
+        ("time_limit", [secs]) => Ok(invariants::time_limit(Duration::from_secs_f64(parse_arg(text, secs)?))),
+        ("max_process_size", [proc @ ("sender" | "receiver"), limit]) => Ok(invariants::max_process_size(
+            format!("{proc}-node"),
+            proc.to_string(),
+            parse_arg(text, limit)?,
+        )),
+        _ => Err(format!("Unknown invariant: {text}")),
+    }
+}
+
+impl McScenario {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut scenario = Self {
+            name: String::new(),
+            guarantees: GUARANTEES.map(String::from).to_vec(),
+            message_count: 2,
+            drop_rate: 0.,
+            dupl_rate: 0.,
+            goals: Vec::new(),
+            prunes: Vec::new(),
+            invariants: Vec::new(),
+        };
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or(format!("Expected key = value: {line}"))?;
+            let bad_value = || format!("Bad value of {key}: {value}");
+            match key {
+                "name" => scenario.name = value.to_string(),
+                "guarantees" => {
+                    scenario.guarantees = value.split_whitespace().map(String::from).collect();
+                    if let Some(g) = scenario.guarantees.iter().find(|g| !GUARANTEES.contains(&g.as_str())) {
+                        return Err(format!("Unknown guarantee: {g}"));
+                    }
+                }
+                "messages" => scenario.message_count = value.parse().map_err(|_| bad_value())?,
+                "drop_rate" => scenario.drop_rate = value.parse().map_err(|_| bad_value())?,
+                "dupl_rate" => scenario.dupl_rate = value.parse().map_err(|_| bad_value())?,
+                // predicates are built once here to report errors at load time
+                "goal" => {
+                    let _ = build_goal(value)?;
+                    scenario.goals.push(value.to_string());
+                }
+                "prune" => {
+                    let _ = build_prune(value)?;
+                    scenario.prunes.push(value.to_string());
+                }
+                "invariant" => {
+                    let _ = build_invariant(value)?;
+                    scenario.invariants.push(value.to_string());
+                }
+                _ => return Err(format!("Unknown key: {key}")),
+            }
+        }
+        if scenario.name.is_empty() {
+            return Err("Scenario name is not set".to_string());
+        }
+        if scenario.goals.is_empty() {
+            scenario.goals.push("no_events".to_string());
+        }
+        Ok(scenario)
+    }
+
+    pub fn runs_for(&self, guarantee: &str) -> bool {
+        self.guarantees.iter().any(|g| g == guarantee)
+    }
+
+    pub fn goal(&self) -> GoalFn {
+        goals::any_goal(self.goals.iter().map(|goal| build_goal(goal).unwrap()).collect())
+    }
+
+    pub fn prune(&self) -> PruneFn {
+        prunes::any_prune(self.prunes.iter().map(|prune| build_prune(prune).unwrap()).collect())
+    }
+
+    pub fn invariants(&self) -> Vec<InvariantFn> {
+        self.invariants
+            .iter()
+            .map(|invariant| build_invariant(invariant).unwrap())
+            .collect()
+    }
+}
+
+pub fn load_mc_scenario(path: &str) -> Result<McScenario, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read {path}: {e}"))?;
+    McScenario::parse(&text).map_err(|e| format!("Bad model checking scenario {path}: {e}"))
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub const GUARANTEES: [&str; 4] = ["AMO", "ALO", "EO", "EOO"];
+
+/// Group of resource consumption measurements checked and reported by overhead tests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverheadMetric {
+    All,
+    /// Memory of sender and receiver
+    Memory,
+    /// Network message count and traffic
+    Traffic,
+    /// Throughput and goodput
+    Throughput,
+}
+
+impl OverheadMetric {
+    pub const NAMES: [&'static str; 4] = ["all", "memory", "traffic", "throughput"];
+
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "memory" => Self::Memory,
+            "traffic" => Self::Traffic,
+            "throughput" => Self::Throughput,
+            _ => Self::All,
+        }
+    }
+
+    /// Returns whether the given metric should be checked and reported.
+    pub fn includes(self, metric: Self) -> bool {
+        self == Self::All || self == metric
+    }
+}
+
+/// Resource consumption measured in a single overhead test run.
+#[derive(Clone, Debug)]
+pub struct OverheadRow {
+    pub guarantee: String,
+    pub faulty: bool,
+    pub message_count: usize,
+    pub sender_mem: u64,
+    pub receiver_mem: u64,
+    pub net_message_count: u64,
+    pub net_traffic: u64,
+    pub net_bytes_delivered: u64,
+    pub throughput: f64,
+    pub goodput: f64,
+}
+
+// overhead tests are plain functions, so their measurements are collected here
+// to be reported after all tests complete, the tests may run in different threads
+static OVERHEAD_ROWS: Mutex<Vec<OverheadRow>> = Mutex::new(Vec::new());
+
+pub fn record_overhead(row: OverheadRow) {
+    OVERHEAD_ROWS.lock().unwrap().push(row);
+}
+
+/// Returns all measurements recorded so far and clears the collection.
+pub fn take_overhead_rows() -> Vec<OverheadRow> {
+    OVERHEAD_ROWS.lock().unwrap().drain(..).collect()
+}
+
+/// Writes the measurements to CSV files, one row per overhead test run.
+///
+/// The columns are fixed, so the runs of each guarantee and network conditions are written to a separate file
+/// named after `path` with the guarantee and `normal` or `faulty` suffix, e.g. `overhead-EO-faulty.csv`
+/// for `overhead.csv`. Files are written only for the runs that were made.
+pub fn write_overhead_csv(path: &str, rows: &[OverheadRow]) -> Result<(), String> {
+    for guarantee in GUARANTEES {
+        for faulty in [false, true] {
+            let runs: Vec<&OverheadRow> = rows
+                .iter()
+                .filter(|r| r.guarantee == guarantee && r.faulty == faulty)
+                .collect();
+            if runs.is_empty() {
+                continue;
+            }
+            let mut text =
+                String::from("message_count,sender_mem,receiver_mem,net_message_count,net_traffic,throughput\n");
+            for r in runs {
+                text.push_str(&format!(
+                    "{},{},{},{},{},{}\n",
+                    r.message_count, r.sender_mem, r.receiver_mem, r.net_message_count, r.net_traffic, r.throughput
+                ));
+            }
+            let path = overhead_csv_path(path, guarantee, faulty);
+            fs::write(&path, text).map_err(|e| format!("Cannot write overhead CSV {path}: {e}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the path of the CSV file with the runs of the guarantee, see [`write_overhead_csv`].
+fn overhead_csv_path(path: &str, guarantee: &str, faulty: bool) -> String {
+    let network = if faulty { "faulty" } else { "normal" };
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{guarantee}-{network}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{guarantee}-{network}"),
+    };
+    path.with_file_name(name).to_string_lossy().to_string()
+}
+
+/// Returns the expected minimum number of network messages needed to provide the guarantee
+/// for `message_count` messages when each network message is lost with probability `loss_rate`.
+///
+/// At most once needs a single transmission per message and no acknowledgements.
+/// Other guarantees need a message to be retransmitted until both it and its acknowledgement get through,
+/// which takes `1 / (1 - loss_rate)^2` transmissions on average, with an acknowledgement sent for each delivered copy.
+pub fn predict_min_messages(guarantee: &str, message_count: usize, loss_rate: f64) -> f64 {
+    let n = message_count as f64;
+    let pass_rate = 1. - loss_rate;
+    match guarantee {
+        "AMO" => n,
+        _ => n / pass_rate.powi(2) + n / pass_rate,
+    }
+}
+
+/// Prints a table comparing the overhead of all guarantees side by side per message count.
+pub fn print_overhead_summary(rows: &[OverheadRow]) {
+    if rows.is_empty() {
+        return;
+    }
+    println!("OVERHEAD SUMMARY");
+    for faulty in [false, true] {
+        let mut message_counts: Vec<usize> = rows
+            .iter()
+            .filter(|r| r.faulty == faulty)
+            .map(|r| r.message_count)
+            .collect();
+        message_counts.sort();
+        message_counts.dedup();
+        if message_counts.is_empty() {
+            continue;
+        }
+        println!("\n{}\n", if faulty { "FAULTY" } else { "NORMAL" });
+        println!(
+            "{:<6} {:<9} {:<9} {:<9} {:<9} {:<9} {:<9} {:<10} {:<7}",
+            "Count", "Guarantee", "Send Mem", "Recv Mem", "Messages", "Traffic", "Delivered", "Throughput", "Goodput"
+        );
+        for message_count in message_counts {
+            for guarantee in GUARANTEES {
+                let row = rows
+                    .iter()
+                    .find(|r| r.faulty == faulty && r.message_count == message_count && r.guarantee == guarantee);
+                if let Some(r) = row {
+                    println!(
+                        "{:<6} {:<9} {:<9} {:<9} {:<9} {:<9} {:<9} {:<10.3} {:.3}",
+                        message_count,
+                        guarantee,
+                        r.sender_mem,
+                        r.receiver_mem,
+                        r.net_message_count,
+                        r.net_traffic,
+                        r.net_bytes_delivered,
+                        r.throughput,
+                        r.goodput
+                    );
+                }
+            }
+        }
+    }
+    println!();
+}
@@ -0,0 +1,134 @@
+//! Predicates of the model checker in addition to the standard ones from [`anysystem::mc::predicates`],
+//! which are re-exported from the modules of the same names.
+
+/// Goals check if state is final.
+pub mod goals {
+    pub use anysystem::mc::predicates::goals::*;
+
+    use anysystem::mc::{GoalFn, McState};
+    use anysystem::process::StringProcessState;
+    use sugars::boxed;
+
+    /// Checks if the given process produced exactly `k` local messages with the given data.
+    ///
+    /// Unlike [`got_n_local_messages`] it is keyed on a particular message, so duplicates of one message
+    /// do not make up for another one not delivered.
+    pub fn delivered_exactly<S, D>(node: S, proc: S, data: D, k: usize) -> GoalFn
+    where
+        S: Into<String>,
+        D: Into<String>,
+    {
+        let node = node.into();
+        let proc = proc.into();
+        let data = data.into();
+        boxed!(move |state: &McState| {
+            let local_outbox = &state.node_states[&node].proc_states[&proc].local_outbox;
+            if local_outbox.iter().filter(|msg| msg.data == data).count() == k {
+                Some(format!("{proc} produced message {data} {k} times"))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Checks if the state of the given process matches the predicate, e.g. the receiver buffers a message
+    /// after a gap in the sequence numbers.
+    ///
+    /// The predicate gets the state returned by `get_state` of the process, which is JSON for the Python processes.
+    pub fn process_state_matches<S, P>(node: S, proc: S, predicate: P) -> GoalFn
+    where
+        S: Into<String>,
+        P: Fn(&str) -> bool + 'static,
+    {
+        let node = node.into();
+        let proc = proc.into();
+        boxed!(move |state: &McState| {
+            let proc_state = &state.node_states[&node].proc_states[&proc].proc_state;
+            let matches = match proc_state.downcast_ref::<StringProcessState>() {
+                Some(data) => predicate(data),
+                None => predicate(&format!("{proc_state:?}")),
+            };
+            matches.then(|| format!("{proc} state matches the predicate"))
+        })
+    }
+
+    /// Checks if the given goal is not reached.
+    pub fn not(goal: GoalFn) -> GoalFn {
+        boxed!(move |state: &McState| match goal(state) {
+            Some(_) => None,
+            None => Some("negated goal is not reached".to_string()),
+        })
+    }
+}
+
+/// Invariants check whether state is correct or not.
+pub mod invariants {
+    pub use anysystem::mc::predicates::invariants::*;
+
+    use anysystem::mc::{InvariantFn, McState};
+    use anysystem::process::StringProcessState;
+    use sugars::boxed;
+
+    /// Checks that the serialized state of the given process does not exceed `limit` bytes.
+    ///
+    /// The state is the one returned by `get_state` of the process, so it includes everything the process keeps.
+    pub fn max_process_size<S>(node: S, proc: S, limit: usize) -> InvariantFn
+    where
+        S: Into<String>,
+    {
+        let node = node.into();
+        let proc = proc.into();
+        boxed!(move |state: &McState| {
+            let proc_state = &state.node_states[&node].proc_states[&proc].proc_state;
+            let size = match proc_state.downcast_ref::<StringProcessState>() {
+                Some(data) => data.len(),
+                None => format!("{proc_state:?}").len(),
+            };
+            if size > limit {
+                Err(format!("{proc} state size {size} exceeds maximum allowed size {limit}"))
+            } else {
+                Ok(())
+            }
+        })
+    }
+}
+
+/// Prunes cut execution branches if further analysis is considered unnecessary or computation-heavy.
+pub mod prunes {
+    pub use anysystem::mc::predicates::prunes::*;
+
+    use anysystem::logger::LogEntry;
+    use anysystem::mc::{McState, PruneFn};
+    use sugars::boxed;
+
+    /// Prunes states in which the processes produced more than `n` local messages in total.
+    ///
+    /// It is [`events_limit`] over [`LogEntry::is_mc_local_message_sent`], which bounds the exploration
+    /// after `n` deliveries regardless of the network events leading to them.
+    pub fn deliveries_limit(n: usize) -> PruneFn {
+        events_limit(LogEntry::is_mc_local_message_sent, n)
+    }
+
+    /// Prunes states not matched by the given prune.
+    ///
+    /// Combined with [`all_prune`] it focuses the search on faulty branches, e.g.
+    /// `all_prune(vec![state_depth(3), not(events_limit(LogEntry::is_mc_message_dropped, 0))])`
+    /// stops the branches without a dropped message after 3 steps.
+    pub fn not(prune: PruneFn) -> PruneFn {
+        boxed!(move |state: &McState| match prune(state) {
+            Some(_) => None,
+            None => Some("negated prune is not matched".to_string()),
+        })
+    }
+
+    /// Prunes states matched by all of the given prunes.
+    pub fn all_prune(prunes: Vec<PruneFn>) -> PruneFn {
+        boxed!(move |state: &McState| {
+            let mut reasons = Vec::with_capacity(prunes.len());
+            for prune in &prunes {
+                reasons.push(prune(state)?);
+            }
+            Some(reasons.join(" and "))
+        })
+    }
+}
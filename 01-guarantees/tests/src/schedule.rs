@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+use anysystem::logger::LogEntry;
+use anysystem::System;
+
+/// Network decision made for a single message sent between different nodes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decision {
+    Deliver(f64),
+    Drop,
+    Duplicate(f64),
+}
+
+/// Sequence of network decisions for the messages in the order they were sent, along with the seed of the run
+/// and the hash seed of the Python interpreter.
+///
+/// Replaying a schedule recorded from a run with the same seed reproduces the run
+/// regardless of the network configuration. The iteration order of Python sets and dicts of strings depends on
+/// the hash seed, which differs from the seed of a chaos monkey run, so it is recorded too and must be set
+/// before the interpreter starts (see [`Schedule::python_hash_seed`]). The schedule is stored as text,
+/// one decision per line:
+///
+/// ```text
+/// seed 123
+/// hash_seed 42
+/// deliver 1.734
+/// drop
+/// duplicate 2.105
+/// ```
+///
+/// Decisions are switched between simulation steps, so if a single event makes a process send several messages,
+/// all of them get the decision of the first one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    pub seed: u64,
+    /// Missing in the schedules saved before the hash seed was recorded.
+    pub hash_seed: Option<u64>,
+    pub decisions: Vec<Decision>,
+}
+
+impl Schedule {
+    /// Extracts the decisions made by the network so far from the system trace.
+    pub fn record(sys: &System, seed: u64) -> Self {
+        let mut sent = Vec::new();
+        let mut dropped = HashMap::new();
+        let mut received: HashMap<String, Vec<f64>> = HashMap::new();
+        for entry in sys.logger().trace() {
+            match entry {
+                LogEntry::MessageSent {
+                    time,
+                    msg_id,
+                    src_node,
+                    dst_node,
+                    ..
+                } if src_node != dst_node => sent.push((msg_id.clone(), *time)),
+                LogEntry::MessageDropped { msg_id, .. } => {
+                    dropped.insert(msg_id.clone(), true);
+                }
+                LogEntry::MessageReceived { time, msg_id, .. } => {
+                    received.entry(msg_id.clone()).or_default().push(*time);
+                }
+                _ => {}
+            }
+        }
+        let decisions = sent
+            .into_iter()
+            .map(|(msg_id, sent_time)| {
+                let receive_times = received.get(&msg_id).cloned().unwrap_or_default();
+                if dropped.contains_key(&msg_id) || receive_times.is_empty() {
+                    Decision::Drop
+                } else if receive_times.len() == 1 {
+                    Decision::Deliver(receive_times[0] - sent_time)
+                } else {
+                    Decision::Duplicate(receive_times[0] - sent_time)
+                }
+            })
+            .collect();
+        Self {
+            seed,
+            hash_seed: Self::python_hash_seed(),
+            decisions,
+        }
+    }
+
+    /// Returns the hash seed of the Python interpreter, which is fixed with `PYTHONHASHSEED` at startup.
+    pub fn python_hash_seed() -> Option<u64> {
+        env::var("PYTHONHASHSEED").ok().and_then(|seed| seed.parse().ok())
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Cannot read schedule {path}: {e}"))?;
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty()).peekable();
+        let seed = lines
+            .next()
+            .and_then(|line| line.strip_prefix("seed "))
+            .and_then(|seed| seed.trim().parse().ok())
+            .ok_or(format!("Schedule {path} does not start with seed"))?;
+        let hash_seed = match lines.next_if(|line| line.starts_with("hash_seed ")) {
+            Some(line) => Some(
+                line["hash_seed ".len()..]
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Bad hash seed in schedule line: {line}"))?,
+            ),
+            None => None,
+        };
+        let mut decisions = Vec::new();
+        for line in lines {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let delay = || -> Result<f64, String> {
+                parts
+                    .get(1)
+                    .and_then(|delay| delay.parse().ok())
+                    .ok_or(format!("Bad delay in schedule line: {line}"))
+            };
+            let decision = match parts[0] {
+                "deliver" => Decision::Deliver(delay()?),
+                "drop" => Decision::Drop,
+                "duplicate" => Decision::Duplicate(delay()?),
+                _ => return Err(format!("Unknown decision in schedule line: {line}")),
+            };
+            decisions.push(decision);
+        }
+        Ok(Self {
+            seed,
+            hash_seed,
+            decisions,
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut text = format!("seed {}\n", self.seed);
+        if let Some(hash_seed) = self.hash_seed {
+            text.push_str(&format!("hash_seed {hash_seed}\n"));
+        }
+        for decision in &self.decisions {
+            match decision {
+                Decision::Deliver(delay) => text.push_str(&format!("deliver {delay}\n")),
+                Decision::Drop => text.push_str("drop\n"),
+                Decision::Duplicate(delay) => text.push_str(&format!("duplicate {delay}\n")),
+            }
+        }
+        fs::write(path, text).map_err(|e| format!("Cannot write schedule {path}: {e}"))
+    }
+
+    /// Configures the network to make the scheduled decision for the next message.
+    /// Messages beyond the end of the schedule are delivered with the current delay.
+    pub fn apply(&self, sys: &mut System) {
+        let mut net = sys.network();
+        let next = net.network_message_count() as usize;
+        match self.decisions.get(next) {
+            Some(Decision::Deliver(delay)) => {
+                net.set_drop_rate(0.);
+                net.set_dupl_rate(0.);
+                net.set_delay(*delay);
+            }
+            Some(Decision::Drop) => {
+                net.set_drop_rate(1.);
+                net.set_dupl_rate(0.);
+            }
+            Some(Decision::Duplicate(delay)) => {
+                net.set_drop_rate(0.);
+                net.set_dupl_rate(1.);
+                net.set_delay(*delay);
+            }
+            None => {
+                net.set_drop_rate(0.);
+                net.set_dupl_rate(0.);
+            }
+        }
+    }
+}
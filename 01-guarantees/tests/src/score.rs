@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::overhead::GUARANTEES;
+
+/// Names of the guarantees as they appear in the test names.
+pub const GUARANTEE_NAMES: [&str; 4] = ["AT MOST ONCE", "AT LEAST ONCE", "EXACTLY ONCE", "EXACTLY ONCE ORDERED"];
+
+/// Rubric used to compute the score from the test results.
+///
+/// The score starts from `max_score` and is reduced by the weight of each guarantee with a failed test (not counting
+/// the overhead tests), by `overhead_penalty` once if any overhead test fails, and by the weights of failed hidden
+/// tests. The default rubric of the homework can be changed with a file passed with `--score-config`,
+/// one `key = value` pair per line:
+///
+/// ```text
+/// # ordered delivery is worth more than the other guarantees
+/// max_score = 10
+/// weight = 2
+/// weight EOO = 3
+/// overhead_penalty = 1
+/// ```
+///
+/// Here `weight` sets the weight of all guarantees and `weight <guarantee>` of a single one.
+#[derive(Clone, Debug)]
+pub struct ScoreConfig {
+    pub max_score: f32,
+    /// Weights of the guarantees by their names in the test names.
+    pub guarantee_weights: BTreeMap<String, f32>,
+    pub overhead_penalty: f32,
+}
+
+impl Default for ScoreConfig {
+    fn default() -> Self {
+        Self {
+            max_score: 9.,
+            guarantee_weights: GUARANTEE_NAMES.iter().map(|name| (name.to_string(), 2.)).collect(),
+            overhead_penalty: 1.,
+        }
+    }
+}
+
+impl ScoreConfig {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or(format!("Expected key = value: {line}"))?;
+            let value: f32 = value.parse().map_err(|_| format!("Bad value of {key}: {value}"))?;
+            match key.split_whitespace().collect::<Vec<_>>()[..] {
+                ["max_score"] => config.max_score = value,
+                ["overhead_penalty"] => config.overhead_penalty = value,
+                ["weight"] => config.guarantee_weights.values_mut().for_each(|weight| *weight = value),
+                ["weight", guarantee] => {
+                    let i = GUARANTEES
+                        .iter()
+                        .position(|g| *g == guarantee)
+                        .ok_or(format!("Unknown guarantee: {guarantee}"))?;
+                    config.guarantee_weights.insert(GUARANTEE_NAMES[i].to_string(), value);
+                }
+                _ => return Err(format!("Unknown key: {key}")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+pub fn load_score_config(path: &str) -> Result<ScoreConfig, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read {path}: {e}"))?;
+    ScoreConfig::parse(&text).map_err(|e| format!("Bad score config {path}: {e}"))
+}
@@ -0,0 +1,118 @@
+use std::fs;
+
+use anysystem::test::TestResult;
+
+/// Static check of the solution source code: constructs which the solution must not or must contain.
+///
+/// Patterns are matched as plain substrings of the source lines, so the check is language-agnostic,
+/// but it also matches the patterns in comments and string literals.
+#[derive(Clone, Copy, Debug)]
+pub struct SourceCheck<'a> {
+    pub forbidden: &'a [&'a str],
+    pub required: &'a [&'a str],
+}
+
+impl SourceCheck<'_> {
+    /// Constructs not allowed in the solutions passing the overhead tests by default: bitwise shifts.
+    pub const DEFAULT_FORBIDDEN: [&'static str; 2] = ["<<", ">>"];
+
+    pub fn check(&self, path: &str) -> TestResult {
+        assert_source_excludes(path, self.forbidden)?;
+        assert_source_includes(path, self.required)
+    }
+}
+
+/// Patterns of [`SourceCheck`] loaded from a file passed with `--source-check`, one `key = pattern` pair per line:
+///
+/// ```text
+/// # solutions must not use shifts and must use sequence numbers
+/// forbid = <<
+/// forbid = >>
+/// require = seq
+/// ```
+///
+/// The file replaces the default patterns, so an empty file disables the check.
+#[derive(Clone, Debug)]
+pub struct SourceCheckConfig {
+    pub forbidden: Vec<String>,
+    pub required: Vec<String>,
+}
+
+impl Default for SourceCheckConfig {
+    fn default() -> Self {
+        Self {
+            forbidden: SourceCheck::DEFAULT_FORBIDDEN.iter().map(|p| p.to_string()).collect(),
+            required: Vec::new(),
+        }
+    }
+}
+
+impl SourceCheckConfig {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self {
+            forbidden: Vec::new(),
+            required: Vec::new(),
+        };
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, pattern) = line
+                .split_once('=')
+                .map(|(key, pattern)| (key.trim(), pattern.trim()))
+                .ok_or(format!("Expected key = pattern: {line}"))?;
+            if pattern.is_empty() {
+                return Err(format!("Empty pattern of {key}"));
+            }
+            match key {
+                "forbid" => config.forbidden.push(pattern.to_string()),
+                "require" => config.required.push(pattern.to_string()),
+                _ => return Err(format!("Unknown key: {key}")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+pub fn load_source_check_config(path: &str) -> Result<SourceCheckConfig, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read {path}: {e}"))?;
+    SourceCheckConfig::parse(&text).map_err(|e| format!("Bad source check config {path}: {e}"))
+}
+
+/// Fails if the source contains any of the patterns, reporting the first match and its line.
+pub fn assert_source_excludes(path: &str, patterns: &[&str]) -> TestResult {
+    let source = read_source(path)?;
+    for (i, line) in source.lines().enumerate() {
+        if let Some(pattern) = patterns.iter().find(|pattern| line.contains(*pattern)) {
+            return Err(format!(
+                "Implementation contains forbidden {} at line {}: {}",
+                pattern,
+                i + 1,
+                line.trim()
+            ));
+        }
+    }
+    Ok(true)
+}
+
+/// Fails if the source does not contain some of the patterns.
+pub fn assert_source_includes(path: &str, patterns: &[&str]) -> TestResult {
+    let source = read_source(path)?;
+    let missing: Vec<&str> = patterns
+        .iter()
+        .copied()
+        .filter(|pattern| !source.contains(pattern))
+        .collect();
+    if missing.is_empty() {
+        Ok(true)
+    } else {
+        Err(format!(
+            "Implementation does not contain required {}",
+            missing.join(", ")
+        ))
+    }
+}
+
+fn read_source(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Cannot read implementation {path}: {e}"))
+}
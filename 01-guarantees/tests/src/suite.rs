@@ -0,0 +1,300 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+use anysystem::test::TestResult;
+
+/// Environment variable with the name of the test to run in a child process started by the suite
+/// (see [`TestSuite::set_timeout`]).
+pub const TEST_PROCESS_VAR: &str = "DISTSYS_GUARANTEES_TEST";
+
+/// Lines enclosing the result of a test run in a child process in its output.
+const RESULT_BEGIN: &str = "--- DISTSYS_GUARANTEES_RESULT BEGIN ---";
+const RESULT_END: &str = "--- DISTSYS_GUARANTEES_RESULT END ---";
+
+struct Test<T> {
+    name: String,
+    func: fn(&T) -> TestResult,
+    config: T,
+}
+
+/// Outcome of a test run by the suite.
+struct TestRun {
+    name: String,
+    result: TestResult,
+    duration: f64,
+}
+
+/// A set of tests supposed to be run together.
+///
+/// Same as [`anysystem::test::TestSuite`], but can also run the tests in parallel (see [`TestSuite::run_parallel`])
+/// and report the results in a structured form to be processed by other tools (see [`TestSuite::run_with_report`]).
+pub struct TestSuite<T> {
+    tests: Vec<Test<T>>,
+    timeout: Option<Duration>,
+    filter: Option<Regex>,
+}
+
+impl<T: Sync> TestSuite<T> {
+    pub fn new() -> Self {
+        Self {
+            tests: Vec::new(),
+            timeout: None,
+            filter: None,
+        }
+    }
+
+    /// Limits the real time of each test, a test exceeding it is stopped and considered failed.
+    ///
+    /// A test stuck in a solution cannot be interrupted inside the process, so with the timeout each test
+    /// is run in a child process executing the same command with [`TEST_PROCESS_VAR`] set to the test name.
+    /// The program should then run only this test with [`TestSuite::run_child_test`]. The child process is killed
+    /// on timeout, and its output is printed once it completes.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Restricts the runs of the suite to the tests with names matching the pattern.
+    pub fn set_filter(&mut self, pattern: Regex) {
+        self.filter = Some(pattern);
+    }
+
+    pub fn add(&mut self, name: &str, f: fn(&T) -> TestResult, config: T) {
+        self.tests.push(Test {
+            name: name.to_string(),
+            func: f,
+            config,
+        });
+    }
+
+    /// Executes the test suite by running each test in turn.
+    ///
+    /// Collects and prints the result of each test, and prints the summary in the end.
+    /// Returns whether all tests are passed and results for each test.
+    pub fn run(&mut self) -> (bool, BTreeMap<String, TestResult>) {
+        self.run_parallel(1)
+    }
+
+    /// Same as [`TestSuite::run`] but runs only the tests with names matching the pattern.
+    pub fn run_matching(&mut self, pattern: &Regex) -> (bool, BTreeMap<String, TestResult>) {
+        self.set_filter(pattern.clone());
+        self.run()
+    }
+
+    /// Same as [`TestSuite::run`] but runs the tests in the given number of threads.
+    ///
+    /// Each test builds its own systems, so the tests do not share any state and their results do not depend
+    /// on the number of threads. The output of the tests running at the same time is interleaved,
+    /// so the result of each test is printed along with its name.
+    pub fn run_parallel(&mut self, threads: usize) -> (bool, BTreeMap<String, TestResult>) {
+        let runs = self.run_all(threads);
+        let all_passed = runs.iter().all(|run| run.result.is_ok());
+        let results = runs.into_iter().map(|run| (run.name, run.result)).collect();
+        (all_passed, results)
+    }
+
+    /// Same as [`TestSuite::run_parallel`] but also returns the report with the name, result, duration in seconds
+    /// and error message of each test in the order they were added.
+    pub fn run_with_report(&mut self, threads: usize) -> (BTreeMap<String, TestResult>, Value) {
+        let runs = self.run_all(threads);
+        let report = runs
+            .iter()
+            .map(|run| {
+                json!({
+                    "name": run.name,
+                    "passed": run.result.is_ok(),
+                    "duration": run.duration,
+                    "error": run.result.as_ref().err(),
+                })
+            })
+            .collect();
+        let results = runs.into_iter().map(|run| (run.name, run.result)).collect();
+        (results, Value::Array(report))
+    }
+
+    /// Runs the specified test and prints its result.
+    pub fn run_test(&mut self, name: &str) {
+        for test in &self.tests {
+            if test.name == name {
+                println!("\n--- {} ---\n", test.name);
+                match (test.func)(&test.config) {
+                    Ok(_) => println!("\nPASSED\n"),
+                    Err(e) => println!("\nFAILED: {e}\n"),
+                }
+            }
+        }
+    }
+
+    /// Runs the specified test in a child process started by the suite (see [`TestSuite::set_timeout`]).
+    ///
+    /// The result is printed after the test output between the [`RESULT_BEGIN`] and [`RESULT_END`] lines,
+    /// so that the suite gets multi-line error messages intact.
+    pub fn run_child_test(&mut self, name: &str) {
+        for test in &self.tests {
+            if test.name == name {
+                let result = (test.func)(&test.config);
+                println!("{RESULT_BEGIN}");
+                match result {
+                    Ok(_) => println!("PASSED"),
+                    Err(e) => println!("FAILED: {e}"),
+                }
+                println!("{RESULT_END}");
+            }
+        }
+    }
+
+    fn run_all(&mut self, threads: usize) -> Vec<TestRun> {
+        let tests: Vec<&Test<T>> = self
+            .tests
+            .iter()
+            .filter(|test| self.filter.as_ref().is_none_or(|filter| filter.is_match(&test.name)))
+            .collect();
+        let runs = if threads <= 1 {
+            tests
+                .iter()
+                .map(|test| {
+                    println!("\n--- {} ---\n", test.name);
+                    let run = test.run(self.timeout);
+                    match &run.result {
+                        Ok(_) => println!("\nPASSED\n"),
+                        Err(e) => println!("\nFAILED: {e}\n"),
+                    }
+                    run
+                })
+                .collect()
+        } else {
+            self.run_in_threads(&tests, threads)
+        };
+        let passed_count = runs.iter().filter(|run| run.result.is_ok()).count();
+        println!("-------------------------------------------------------------------------------");
+        println!("\nPassed {passed_count} from {} tests\n", runs.len());
+        if passed_count < runs.len() {
+            println!("Failed tests:");
+            let failed: BTreeMap<&str, &String> = runs
+                .iter()
+                .filter_map(|run| Some((run.name.as_str(), run.result.as_ref().err()?)))
+                .collect();
+            for (test, e) in failed {
+                println!("- {test}: {e}")
+            }
+            println!();
+        }
+        runs
+    }
+
+    /// Runs the tests in the given number of threads, each thread takes the next test once it completes the previous one.
+    fn run_in_threads(&self, tests: &[&Test<T>], threads: usize) -> Vec<TestRun> {
+        let next_test = AtomicUsize::new(0);
+        let mut runs: Vec<(usize, TestRun)> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|_| {
+                    let next_test = &next_test;
+                    scope.spawn(move || {
+                        let mut runs = Vec::new();
+                        loop {
+                            let i = next_test.fetch_add(1, Ordering::SeqCst);
+                            let Some(test) = tests.get(i) else {
+                                break;
+                            };
+                            println!("\n--- {} ---\n", test.name);
+                            let run = test.run(self.timeout);
+                            match &run.result {
+                                Ok(_) => println!("\n{}: PASSED\n", test.name),
+                                Err(e) => println!("\n{}: FAILED: {e}\n", test.name),
+                            }
+                            runs.push((i, run));
+                        }
+                        runs
+                    })
+                })
+                .collect();
+            handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+        });
+        runs.sort_by_key(|(i, _)| *i);
+        runs.into_iter().map(|(_, run)| run).collect()
+    }
+}
+
+impl<T> Test<T> {
+    fn run(&self, timeout: Option<Duration>) -> TestRun {
+        let start_time = Instant::now();
+        let result = match timeout {
+            Some(timeout) => self.run_in_process(timeout),
+            None => (self.func)(&self.config),
+        };
+        TestRun {
+            name: self.name.clone(),
+            result,
+            duration: start_time.elapsed().as_secs_f64(),
+        }
+    }
+
+    fn run_in_process(&self, timeout: Duration) -> TestResult {
+        let exe = env::current_exe().map_err(|e| format!("Cannot start test process: {e}"))?;
+        let mut child = Command::new(exe)
+            .args(env::args_os().skip(1))
+            .env(TEST_PROCESS_VAR, &self.name)
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Cannot start test process: {e}"))?;
+        // the output is read concurrently, otherwise the child blocks once the pipe is full
+        let mut stdout = child.stdout.take().unwrap();
+        let reader = thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stdout.read_to_string(&mut output);
+            output
+        });
+        let start_time = Instant::now();
+        let timed_out = loop {
+            match child.try_wait() {
+                Ok(Some(_)) => break false,
+                Ok(None) if start_time.elapsed() > timeout => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    break true;
+                }
+                Ok(None) => thread::sleep(Duration::from_millis(10)),
+                Err(e) => return Err(format!("Cannot wait for test process: {e}")),
+            }
+        };
+        let output = reader.join().unwrap();
+        // the result printed by run_child_test is printed by the suite itself
+        let lines: Vec<&str> = output.lines().collect();
+        let begin = lines
+            .iter()
+            .rposition(|line| *line == RESULT_BEGIN)
+            .filter(|_| !timed_out);
+        let test_output = lines[..begin.unwrap_or(lines.len())].join("\n");
+        if !test_output.trim().is_empty() {
+            println!("{}", test_output.trim_matches('\n'));
+        }
+        if timed_out {
+            return Err(format!("timeout: test is not completed in {}s", timeout.as_secs_f32()));
+        }
+        let result = begin.and_then(|begin| {
+            let end = lines[begin..].iter().position(|line| *line == RESULT_END)?;
+            Some(lines[begin + 1..begin + end].join("\n"))
+        });
+        match result.as_deref() {
+            Some("PASSED") => Ok(true),
+            Some(result) => match result.strip_prefix("FAILED: ") {
+                Some(error) => Err(error.to_string()),
+                None => Err(format!("test process reported a malformed result: {result}")),
+            },
+            None => Err("test process exited without reporting the result".to_string()),
+        }
+    }
+}
+
+impl<T: Sync> Default for TestSuite<T> {
+    fn default() -> Self {
+        TestSuite::new()
+    }
+}
@@ -1,17 +1,40 @@
-use std::fs;
+use std::collections::HashMap;
+use std::thread;
 
-use assertables::assume;
+use assertables::{assume, assume_eq};
 use rand::prelude::*;
 use rand_pcg::Pcg64;
 
+use anysystem::logger::LogEntry;
 use anysystem::test::TestResult;
+use anysystem::{Message, System};
 
-use crate::common::{build_system, check_guarantees, check_overhead, send_messages, TestConfig};
+use crate::common::{
+    assert_sender_idle_after, build_ack_loss_system, build_link_system, build_multi_system, build_partitioned_system,
+    build_system, check_agreement, check_causal_order, check_delivery, check_guarantees, check_max_stall,
+    check_multi_guarantees, check_overhead, check_receipts, dropped_message_count, dump_state,
+    duplicated_message_count, first_and_last_delivery_time, generate_message_texts, generate_non_unique_message_texts,
+    goodput, in_flight_messages, local_messages_with_time, message_count_from, multi_routes, network_messages_sent_by,
+    outbox_growth_profile, overhead_limits, pending_delivered_count, pending_timers, random_string_from,
+    read_local_receipts, restart_receiver, send_local_messages, send_message_burst, send_message_texts, send_messages,
+    send_messages_as, send_messages_with, send_messages_with_deps, send_messages_with_hooks, send_multi_messages,
+    sent_message_count_after, step_for_duration_with_events, step_until_acknowledged, step_until_no_events,
+    step_until_no_events_with, step_until_settled, text_message, traffic_from, warm_throughput, with_event_log,
+    DelayDistribution, DropBudget, DropMatching, DropNth, DropRateSchedule, DuplicateNth, LatencySpike, LinkNetwork,
+    MemoryLimit, MessageHooks, NetworkBytes, SizeHints, SizeHistory, SlowConsumer, TestConfig, RECEIPT_TIP,
+    SPECIAL_CHARACTERS, STORAGE_FIELD,
+};
+use crate::hidden::HiddenTest;
+use crate::overhead::{predict_min_messages, record_overhead, OverheadMetric, OverheadRow};
+use crate::schedule::Schedule;
 
 pub fn test_normal(config: &TestConfig) -> TestResult {
     let mut sys = build_system(config, false);
-    let messages = send_messages(&mut sys, 5);
-    sys.step_until_no_events();
+    let messages = send_messages_as(&mut sys, config, 5);
+    // the sender should stop sending once all messages are delivered and acknowledged
+    step_until_acknowledged(&mut sys, messages.len(), config.max_steps)?;
+    assert_sender_idle_after(&mut sys, 10.)?;
+    step_until_settled(&mut sys, config)?;
     check_guarantees(&mut sys, &messages, config)?;
     // We expect no more than 5 messages from sender in normal network conditions
     let sent_count = sys.sent_message_count("sender");
@@ -23,8 +46,9 @@ pub fn test_normal(config: &TestConfig) -> TestResult {
 
 pub fn test_normal_non_unique(config: &TestConfig) -> TestResult {
     let mut sys = build_system(config, false);
-    let messages = send_messages(&mut sys, 10);
-    sys.step_until_no_events();
+    let texts = generate_non_unique_message_texts(&mut sys, 10);
+    let messages = send_message_texts(&mut sys, texts);
+    step_until_settled(&mut sys, config)?;
     check_guarantees(&mut sys, &messages, config)?;
     // We expect no more than 10 messages from sender in normal network conditions (stable delay, no loss).
     // If solution sends multiple messages without or with too small (<RTT) delay, this results in extra redundant
@@ -36,77 +60,943 @@ pub fn test_normal_non_unique(config: &TestConfig) -> TestResult {
     )
 }
 
+pub fn test_special_characters(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    // the texts need escaping in JSON, so the solution should not build messages by concatenating strings
+    let texts = (0..10)
+        .map(|_| random_string_from(&mut sys, 20, SPECIAL_CHARACTERS))
+        .collect();
+    let messages = send_message_texts(&mut sys, texts);
+    step_until_settled(&mut sys, config)?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+pub fn test_burst(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    sys.network().set_delays(1., 3.);
+    // the whole batch arrives before the sender gets any reply
+    let messages = send_message_burst(&mut sys, 50);
+    step_until_settled(&mut sys, config)?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
 pub fn test_delayed(config: &TestConfig) -> TestResult {
     let mut sys = build_system(config, false);
     sys.network().set_delays(1., 3.);
-    let messages = send_messages(&mut sys, 5);
-    sys.step_until_no_events();
+    let messages = send_messages_as(&mut sys, config, 5);
+    step_until_settled(&mut sys, config)?;
     check_guarantees(&mut sys, &messages, config)
 }
 
+pub fn test_latency_spike(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    let spike = LatencySpike::schedule(&mut sys, 1., 10., 5., (1., 1.));
+    let messages = send_messages_with(&mut sys, 5, |sys| spike.apply(sys));
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| spike.apply(sys))?;
+    check_guarantees(&mut sys, &messages, config)?;
+    // Retransmissions during the spike are expected, but once it has passed
+    // the sender should need at most one more transmission per message
+    let sent_after = sent_message_count_after(&sys, "sender", spike.passed_time());
+    assume!(
+        sent_after <= 5,
+        format!(
+            "Sender sent {} messages after the latency spike passed, expected at most 5",
+            sent_after
+        )
+    )
+}
+
 pub fn test_duplicated(config: &TestConfig) -> TestResult {
     let mut sys = build_system(config, false);
     sys.network().set_dupl_rate(0.3);
-    let messages = send_messages(&mut sys, 5);
-    sys.step_until_no_events();
-    check_guarantees(&mut sys, &messages, config)
+    let messages = send_messages_as(&mut sys, config, 5);
+    step_until_settled(&mut sys, config)?;
+    // the events show which duplicate caused the violation
+    check_guarantees(&mut sys, &messages, config).map_err(|err| with_event_log(&sys, err))
+}
+
+/// Broadcasts the same messages to several receivers, each through its own sender, over the lossy network.
+pub fn test_broadcast(config: &TestConfig) -> TestResult {
+    let routes = multi_routes(3, 3);
+    let mut sys = build_multi_system(config, 3, 3);
+    sys.network().set_drop_rate(0.3);
+    let messages: Vec<Message> = generate_message_texts(&mut sys, 5)
+        .iter()
+        .map(|text| text_message(text))
+        .collect();
+    for (sender, _) in &routes {
+        send_local_messages(&mut sys, sender, &messages);
+    }
+    step_until_no_events(&mut sys, config.max_steps)?;
+    let mut delivered = Vec::new();
+    for (_, receiver) in &routes {
+        let msgs = sys.read_local_messages(receiver);
+        check_delivery(&msgs, &messages, config).map_err(|e| format!("{receiver}: {e}"))?;
+        delivered.push(msgs);
+    }
+    let delivered: Vec<&[Message]> = delivered.iter().map(Vec::as_slice).collect();
+    check_agreement(&delivered)
+}
+
+pub fn test_duplicated_nth(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    sys.network().set_delays(1., 3.);
+    let duplicate = DuplicateNth::new(&mut sys, 0);
+    let messages = send_messages_with(&mut sys, 5, |sys| duplicate.apply(sys));
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| duplicate.apply(sys))?;
+    assume!(
+        duplicated_message_count(&sys) > 0,
+        "The first network message is not duplicated"
+    )?;
+    check_guarantees(&mut sys, &messages, config).map_err(|err| with_event_log(&sys, err))
 }
 
 pub fn test_delayed_duplicated(config: &TestConfig) -> TestResult {
     let mut sys = build_system(config, false);
     sys.network().set_delays(1., 3.);
     sys.network().set_dupl_rate(0.3);
-    let messages = send_messages(&mut sys, 5);
-    sys.step_until_no_events();
-    check_guarantees(&mut sys, &messages, config)
+    let messages = send_messages_as(&mut sys, config, 5);
+    step_until_settled(&mut sys, config)?;
+    // the events show which duplicate caused the violation
+    check_guarantees(&mut sys, &messages, config).map_err(|err| with_event_log(&sys, err))
+}
+
+pub fn test_causal_order(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    sys.network().set_delays(1., 3.);
+    sys.network().set_dupl_rate(0.3);
+    // each message depends on a couple of random earlier ones
+    let (messages, deps) = send_messages_with_deps(
+        &mut sys,
+        20,
+        |_| {},
+        |sys, i| {
+            let mut deps: Vec<usize> = (0..i.min(2)).map(|_| sys.gen_range(0..i)).collect();
+            deps.dedup();
+            deps
+        },
+    );
+    step_until_settled(&mut sys, config)?;
+    let delivered = sys.read_local_messages("receiver");
+    check_causal_order(&delivered, &messages, &deps)?;
+    check_delivery(&delivered, &messages, config)
 }
 
 pub fn test_dropped(config: &TestConfig) -> TestResult {
     let mut sys = build_system(config, false);
     sys.network().set_drop_rate(0.3);
-    let messages = send_messages(&mut sys, 5);
-    sys.step_until_no_events();
+    let messages = send_messages_as(&mut sys, config, 5);
+    step_until_settled(&mut sys, config)?;
+    check_guarantees(&mut sys, &messages, config)?;
+    let dropped = dropped_message_count(&sys);
+    println!(
+        "Network dropped {} and duplicated {} messages",
+        dropped,
+        duplicated_message_count(&sys)
+    );
+    // with few messages the network may drop none of them, which makes the run trivial
+    assume!(
+        dropped > 0,
+        "Network dropped no messages, so the run did not exercise drops (try another --seed)"
+    )
+}
+
+pub fn test_dropped_after_first_ack(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    sys.network().set_delays(1., 3.);
+    let messages = send_message_burst(&mut sys, 10);
+    // the network becomes lossy once the sender gets the first reply from the receiver
+    step_for_duration_with_events(&mut sys, 100., |sys, entry| {
+        if matches!(entry, LogEntry::MessageReceived { dst_proc, .. } if dst_proc == "sender") {
+            sys.network().set_drop_rate(0.3);
+        }
+    });
+    step_until_settled(&mut sys, config)?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+pub fn test_dropped_nth(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    let drop = DropNth::new(&mut sys, 2);
+    // a receiver may hold back the messages following a gap for a while,
+    // so enough messages are sent for it to give up waiting for the lost one
+    let messages = send_messages_with(&mut sys, 30, |sys| drop.apply(sys));
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| drop.apply(sys))?;
+    // only the dropped message is lost, the others are delivered exactly once
+    let mut expected = messages.clone();
+    let lost = expected.remove(2);
+    let delivered = sys.read_local_messages("receiver");
+    assume!(
+        !delivered.iter().any(|msg| msg.data == lost.data),
+        format!("Message {} is delivered, but it was dropped", lost.data)
+    )?;
+    let mut reliable_config = *config;
+    reliable_config.reliable = true;
+    check_delivery(&delivered, &expected, &reliable_config)?;
+    // at most once should not retransmit the lost message
+    let sent_count = sys.sent_message_count("sender");
+    assume!(
+        sent_count == messages.len() as u64,
+        format!(
+            "Sender sent {} messages, expected exactly {} without retransmissions",
+            sent_count,
+            messages.len()
+        )
+    )
+}
+
+pub fn test_dropped_matching(config: &TestConfig) -> TestResult {
+    // the runs are deterministic, so the messages are known beforehand
+    let target = generate_message_texts(&mut build_system(config, false), 30).remove(2);
+    // at most once can not recover the message, while others have to retransmit it despite a few losses
+    let limit = if config.reliable { 3 } else { usize::MAX };
+    let mut drop = DropMatching::new(|msg: &Message| msg.data.contains(&target), limit);
+    let (mut sys, messages, run_count) = run_with_drop_matching(config, 30, &mut drop)?;
+    println!(
+        "Dropped {} network messages with text {} in {} runs",
+        drop.dropped_count(),
+        target,
+        run_count
+    );
+    if config.reliable {
+        return check_guarantees(&mut sys, &messages, config);
+    }
+    let mut expected = messages.clone();
+    let lost = expected.remove(2);
+    let delivered = sys.read_local_messages("receiver");
+    assume!(
+        !delivered.iter().any(|msg| msg.data == lost.data),
+        format!("Message {} is delivered, but all its copies were dropped", lost.data)
+    )?;
+    let mut reliable_config = *config;
+    reliable_config.reliable = true;
+    check_delivery(&delivered, &expected, &reliable_config)
+}
+
+/// Reruns the test until all messages matching the predicate are dropped (see [`DropMatching`]),
+/// returns the system after the last run with the sent messages and the number of runs.
+fn run_with_drop_matching(
+    config: &TestConfig,
+    message_count: usize,
+    drop: &mut DropMatching<impl Fn(&Message) -> bool>,
+) -> Result<(System, Vec<Message>, u32), String> {
+    let mut run_count = 0;
+    loop {
+        let mut sys = build_system(config, false);
+        let messages = send_messages_with(&mut sys, message_count, |sys| drop.apply(sys));
+        step_until_no_events_with(&mut sys, config.max_steps, |sys| drop.apply(sys))?;
+        run_count += 1;
+        if !drop.update(&sys)? {
+            return Ok((sys, messages, run_count));
+        }
+    }
+}
+
+pub fn test_dropped_budget(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    sys.network().set_drop_rate(0.5);
+    let mut budget = DropBudget::new(3);
+    let messages = send_messages_with(&mut sys, 5, |sys| budget.enforce(sys));
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| budget.enforce(sys))?;
+    check_guarantees(&mut sys, &messages, config)?;
+    let dropped = budget.dropped_within_budget(&sys)?;
+    // Each lost message should cost at most one retransmission of every message (e.g. go-back-N),
+    // and a solution that doesn't retransmit shouldn't send anything extra
+    let sent_count = sys.sent_message_count("sender");
+    let sent_limit = if config.reliable { 5 * (dropped + 1) } else { 5 };
+    assume!(
+        sent_count <= sent_limit,
+        format!(
+            "Sender sent {} messages with {} lost, expected at most {}",
+            sent_count, dropped, sent_limit
+        )
+    )
+}
+
+pub fn test_partition(config: &TestConfig) -> TestResult {
+    let (mut sys, partition) = build_partitioned_system(config);
+    sys.network().set_delays(1., 3.);
+    // the nodes are isolated long enough for the sender to retransmit several times
+    let partition = partition.schedule(&[&["sender-node"], &["receiver-node"]], 2., 20.);
+    let messages = send_messages_with(&mut sys, 10, |sys| partition.apply(sys));
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| partition.apply(sys))?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+pub fn test_drop_outage(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    sys.network().set_delays(1., 3.);
+    // almost everything is lost during the outage, the solution has to deliver the rest once it heals
+    let outage = DropRateSchedule::new(vec![(0., 0.), (2., 0.9), (20., 0.)]);
+    let messages = send_messages_with(&mut sys, 10, |sys| outage.apply(sys));
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| outage.apply(sys))?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+pub fn test_overtaken_message(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    // the first message arrives after all the following ones
+    let mut links = LinkNetwork::new(&mut sys, (1., 1.));
+    links.delay_next_message("sender-node", "receiver-node", 10.);
+    let messages = send_messages_with_hooks(
+        &mut sys,
+        5,
+        |sys| links.apply_from(sys, "sender-node"),
+        |sys| links.apply(sys),
+    );
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| links.apply(sys))?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+pub fn test_fifo_link(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    // the delays are wide enough to reorder messages, but the forward link is forced to preserve the send order
+    let mut links = LinkNetwork::new(&mut sys, (1., 5.));
+    links.set_reorder(false);
+    links.deliver_in_order("receiver-node", "sender-node", false);
+    let messages = send_messages_with_hooks(
+        &mut sys,
+        10,
+        |sys| links.apply_from(sys, "sender-node"),
+        |sys| links.apply(sys),
+    );
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| links.apply(sys))?;
+    let trace = sys.logger().trace().clone();
+    let sent: Vec<&String> = trace
+        .iter()
+        .filter_map(|entry| match entry {
+            LogEntry::MessageSent { msg_id, src_node, .. } if src_node == "sender-node" => Some(msg_id),
+            _ => None,
+        })
+        .collect();
+    let received: Vec<usize> = trace
+        .iter()
+        .filter_map(|entry| match entry {
+            LogEntry::MessageReceived { msg_id, src_node, .. } if src_node == "sender-node" => {
+                sent.iter().position(|id| *id == msg_id)
+            }
+            _ => None,
+        })
+        .collect();
+    assume!(
+        received.windows(2).all(|pair| pair[0] <= pair[1]),
+        "Messages from sender are received out of order on the FIFO link"
+    )?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+pub fn test_heavy_tailed_delays(config: &TestConfig) -> TestResult {
+    // most messages arrive within 2 time units, but some of them are delayed many times longer
+    let (mut sys, mut links) = build_link_system(config, (1., 1.));
+    links.set_delay_distribution(DelayDistribution::Exponential { lambda: 1. });
+    let messages = send_messages_with_hooks(
+        &mut sys,
+        20,
+        |sys| links.apply_from(sys, "sender-node"),
+        |sys| links.apply(sys),
+    );
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| links.apply(sys))?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+pub fn test_ack_loss(config: &TestConfig) -> TestResult {
+    // data messages get through, so a sender waiting for every acknowledgement before moving on
+    // must still make progress while most of them are lost
+    let (mut sys, links) = build_ack_loss_system(config, 0.7);
+    let messages = send_messages_with_hooks(
+        &mut sys,
+        10,
+        |sys| links.apply_from(sys, "sender-node"),
+        |sys| links.apply(sys),
+    );
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| links.apply(sys))?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+pub fn test_asymmetric_links(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    // only the forward path is slow and lossy, while the acknowledgements are fast and reliable
+    let mut links = LinkNetwork::new(&mut sys, (1., 1.));
+    links.set_link_delays("sender-node", "receiver-node", 2., 4.);
+    links.set_link_drop_rate("sender-node", "receiver-node", 0.5);
+    links.set_link_delays("receiver-node", "sender-node", 0.5, 0.5);
+    let messages = send_messages_with_hooks(
+        &mut sys,
+        10,
+        |sys| links.apply_from(sys, "sender-node"),
+        |sys| links.apply(sys),
+    );
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| links.apply(sys))?;
+    check_guarantees(&mut sys, &messages, config)?;
+    let dropped_from_receiver = sys
+        .logger()
+        .trace()
+        .iter()
+        .filter(|entry| matches!(entry, LogEntry::MessageDropped { src_node, .. } if src_node == "receiver-node"))
+        .count();
+    assume!(
+        dropped_from_receiver == 0,
+        format!("{dropped_from_receiver} messages from receiver are dropped, but its link is reliable")
+    )
+}
+
+pub fn test_clock_skew(config: &TestConfig) -> TestResult {
+    // the sender clock runs fast, so its timers fire before the expected time, while the receiver clock runs slow
+    let mut run_config = *config;
+    run_config.clock_rates = &[("sender-node", 1.5), ("receiver-node", 0.7)];
+    let mut sys = build_system(&run_config, false);
+    sys.network().set_delays(1., 3.);
+    sys.network().set_dupl_rate(0.3);
+    sys.network().set_drop_rate(0.3);
+    let messages = send_messages(&mut sys, 10);
+    step_until_settled(&mut sys, config)?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+pub fn test_delivery_receipts(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    sys.network().set_drop_rate(0.3);
+    sys.network().set_dupl_rate(0.3);
+    let mut delivered_count = 0;
+    let mut confirmed_count = 0;
+    let mut sent_count = 0;
+    let mut hooks = MessageHooks::default();
+    hooks.on_send(|proc, _| {
+        if proc == "sender" {
+            sent_count += 1
+        }
+    });
+    hooks.on_deliver(|proc, msg| match proc {
+        "receiver" => delivered_count += 1,
+        _ if msg.tip == RECEIPT_TIP => confirmed_count += 1,
+        _ => {}
+    });
+    let messages = send_messages_with(&mut sys, 5, |sys| hooks.fire(sys));
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| hooks.fire(sys))?;
+    drop(hooks);
+    // receipts are optional, but the reported ones must be accurate
+    println!(
+        "Sender sent {} network messages, receiver delivered {} messages, sender confirmed {} of {} messages",
+        sent_count,
+        delivered_count,
+        confirmed_count,
+        messages.len()
+    );
+    let receipts = read_local_receipts(&sys, "sender");
+    check_receipts(&receipts, &local_messages_with_time(&sys, "receiver"))?;
     check_guarantees(&mut sys, &messages, config)
 }
 
+pub fn test_slow_consumer(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    // the application consumes at most a few messages at once and stalls in between
+    let mut consumer = SlowConsumer::new(&mut sys, "receiver", 3, 10., (0.5, 1.5));
+    let messages = send_messages_with(&mut sys, 30, |sys| consumer.apply(sys));
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| consumer.apply(sys))?;
+    let delivered = consumer.finish(&mut sys);
+    check_delivery(&delivered, &messages, config)
+}
+
+pub fn test_receiver_restart(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    sys.network().set_delays(1., 3.);
+    let messages = send_messages(&mut sys, 20);
+    // restart the receiver after it delivers half of the messages
+    while pending_delivered_count(&sys) < messages.len() / 2 && sys.step() {}
+    let mut delivered = sys.read_local_messages("receiver");
+    let lost_count = in_flight_messages(&sys)
+        .iter()
+        .filter(|(_, dst, _, _)| dst == "receiver")
+        .count();
+    println!(
+        "Restarting receiver after {} delivered messages, {} messages in flight to it are lost",
+        delivered.len(),
+        lost_count
+    );
+    let persisted = restart_receiver(&mut sys, config);
+    step_until_settled(&mut sys, config)?;
+    let delivered_after = sys.read_local_messages("receiver");
+    if config.once {
+        let redelivered = delivered_after
+            .iter()
+            .filter(|msg| delivered.iter().any(|d| d.data == msg.data))
+            .count();
+        let reason = if persisted {
+            format!("its persisted `{STORAGE_FIELD}` lacks deduplication data")
+        } else {
+            format!("it persists nothing, its state has no `{STORAGE_FIELD}` field")
+        };
+        assume!(
+            redelivered == 0,
+            format!(
+                "Receiver redelivered {} messages after restart, {}",
+                redelivered, reason
+            )
+        )?;
+    }
+    delivered.extend(delivered_after);
+    check_delivery(&delivered, &messages, config)
+}
+
+pub fn test_deterministic_startup(config: &TestConfig) -> TestResult {
+    let first = dump_state(&build_system(config, false));
+    let second = dump_state(&build_system(config, false));
+    assume!(
+        first == second,
+        format!(
+            "Systems built with the same seed have different initial state:\n{}\n{}",
+            first, second
+        )
+    )
+}
+
 pub fn test_chaos_monkey(config: &TestConfig) -> TestResult {
     let mut rand = Pcg64::seed_from_u64(config.seed);
-    for i in 1..=config.monkeys {
-        let mut run_config = *config;
-        run_config.seed = rand.next_u64();
-        println!("Run {} (seed: {})", i, run_config.seed);
-        let mut sys = build_system(&run_config, false);
-        sys.network().set_delays(1., 3.);
-        sys.network().set_dupl_rate(0.3);
-        sys.network().set_drop_rate(0.3);
-        let messages = send_messages(&mut sys, 50);
-        sys.step_until_no_events();
-        let res = check_guarantees(&mut sys, &messages, &run_config);
-        res.as_ref()?;
+    let seeds: Vec<u64> = (0..config.monkeys).map(|_| rand.next_u64()).collect();
+    if config.chaos_jobs <= 1 {
+        for (i, seed) in seeds.iter().enumerate() {
+            println!("Run {} (seed: {})", i + 1, seed);
+            if let Err((err, schedule)) = run_chaos_monkey(config, *seed) {
+                save_chaos_schedule(config, &schedule)?;
+                shrink_chaos(config, *seed);
+                return Err(err);
+            }
+        }
+        return Ok(true);
+    }
+
+    // runs are distributed between jobs round-robin and their results are reported in the original order
+    let jobs = config.chaos_jobs as usize;
+    println!("Running {} runs in {} jobs", seeds.len(), jobs);
+    let mut results: Vec<(usize, ChaosResult)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..jobs)
+            .map(|job| {
+                let seeds = &seeds;
+                scope.spawn(move || {
+                    (job..seeds.len())
+                        .step_by(jobs)
+                        .map(|i| (i, run_chaos_monkey(config, seeds[i])))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    let failed: Vec<_> = results
+        .into_iter()
+        .filter_map(|(i, res)| res.err().map(|err| (i, err)))
+        .collect();
+    if let Some((i, (err, schedule))) = failed.first() {
+        for (i, (err, _)) in &failed {
+            println!("Run {} (seed: {}) failed: {}", i + 1, seeds[*i], err);
+        }
+        save_chaos_schedule(config, schedule)?;
+        shrink_chaos(config, seeds[*i]);
+        let failed_seeds: Vec<String> = failed.iter().map(|(i, _)| seeds[*i].to_string()).collect();
+        return Err(format!(
+            "Run {} failed: {} (failed seeds: {})",
+            i + 1,
+            err,
+            failed_seeds.join(", ")
+        ));
+    }
+    Ok(true)
+}
+
+/// Result of a chaos monkey run along with the network schedule of the run if it fails.
+type ChaosResult = Result<(), (String, Schedule)>;
+
+/// Runs a single chaos monkey scenario, returning the network schedule of the run if it fails.
+fn run_chaos_monkey(config: &TestConfig, seed: u64) -> ChaosResult {
+    let mut run_config = *config;
+    run_config.seed = seed;
+    let mut sys = build_system(&run_config, false);
+    sys.network().set_delays(1., 3.);
+    sys.network().set_dupl_rate(config.chaos.dupl_rate);
+    sys.network().set_drop_rate(config.chaos.drop_rate);
+    let messages = send_messages(&mut sys, config.chaos.messages);
+    step_until_settled(&mut sys, config)
+        .and_then(|_| check_guarantees(&mut sys, &messages, &run_config))
+        .map(|_| ())
+        .map_err(|err| (err, Schedule::record(&sys, seed)))
+}
+
+/// Reduces the number of messages and the fault rates of a failing chaos monkey run
+/// as long as the run with the same seed still fails, and prints the smallest found scenario.
+///
+/// A smaller scenario consumes the random numbers differently, so it fails for its own reasons,
+/// which are hopefully the same as the original ones.
+pub fn shrink_chaos<'a>(config: &TestConfig<'a>, failing_seed: u64) -> TestConfig<'a> {
+    let mut shrunk = *config;
+    shrunk.seed = failing_seed;
+    while let Some(chaos) = shrunk.chaos.shrink_candidates().into_iter().find(|chaos| {
+        let candidate = TestConfig {
+            chaos: *chaos,
+            ..shrunk
+        };
+        run_chaos_monkey(&candidate, failing_seed).is_err()
+    }) {
+        shrunk.chaos = chaos;
+    }
+    println!(
+        "Smallest failing scenario (seed: {}): {} messages, drop rate {}, duplication rate {}",
+        failing_seed, shrunk.chaos.messages, shrunk.chaos.drop_rate, shrunk.chaos.dupl_rate
+    );
+    shrunk
+}
+
+fn save_chaos_schedule(config: &TestConfig, schedule: &Schedule) -> TestResult {
+    if let Some(path) = config.save_schedule {
+        schedule.save(path)?;
+        println!("Schedule saved to {path}");
     }
     Ok(true)
 }
 
+pub fn test_schedule(config: &TestConfig) -> TestResult {
+    let schedule = Schedule::load(config.schedule.unwrap())?;
+    let mut run_config = *config;
+    run_config.seed = schedule.seed;
+    println!(
+        "Replaying {} network decisions (seed: {})",
+        schedule.decisions.len(),
+        schedule.seed
+    );
+    let mut sys = build_system(&run_config, false);
+    schedule.apply(&mut sys);
+    let messages = send_messages_with(&mut sys, 50, |sys| schedule.apply(sys));
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| schedule.apply(sys))?;
+    check_guarantees(&mut sys, &messages, &run_config)
+}
+
+pub fn test_hidden(config: &TestConfig) -> TestResult {
+    let test = config.hidden_test.unwrap();
+    let mut kwargs = config.process_kwargs.clone();
+    kwargs.extend(test.kwargs.clone());
+    let mut run_config = *config;
+    run_config.process_kwargs = &kwargs;
+    run_config.seed = test.seed.unwrap_or(config.seed);
+    // guarantees that are not reliable with message drops should still be reliable without them,
+    // corrupted messages are discarded by the solution, so they are lost as well
+    run_config.reliable = config.reliable || (test.drop_rate == 0. && test.corrupt_rate == 0.);
+    if test.is_multi() {
+        return test_hidden_multi(test, &run_config);
+    }
+    let (mut sys, mut links) = build_link_system(&run_config, (1., 1.));
+    links.set_drop_rate(&mut sys, test.drop_rate);
+    sys.network().set_dupl_rate(test.dupl_rate);
+    sys.network().set_corrupt_rate(test.corrupt_rate);
+    let messages = match test.delays {
+        // the network itself samples only uniform delays, other distributions are sampled by the processes
+        Some(delays @ (DelayDistribution::Normal { .. } | DelayDistribution::Exponential { .. })) => {
+            links.set_delay_distribution(delays);
+            let messages = send_messages_with_hooks(
+                &mut sys,
+                test.message_count,
+                |sys| links.apply_from(sys, "sender-node"),
+                |sys| links.apply(sys),
+            );
+            step_until_no_events_with(&mut sys, config.max_steps, |sys| links.apply(sys))?;
+            messages
+        }
+        delays => {
+            if let Some(DelayDistribution::Uniform { min, max }) = delays {
+                sys.network().set_delays(min, max);
+            }
+            let messages = send_messages(&mut sys, test.message_count);
+            step_until_settled(&mut sys, config)?;
+            messages
+        }
+    };
+    check_guarantees(&mut sys, &messages, &run_config)?;
+    if let Some(max_sent) = test.max_sent {
+        let sent_count = sys.sent_message_count("sender");
+        assume!(
+            sent_count <= max_sent,
+            format!("Sender sent {} messages, expected at most {}", sent_count, max_sent)
+        )?;
+    }
+    Ok(true)
+}
+
+/// Runs a hidden test scenario with several senders or receivers.
+fn test_hidden_multi(test: &HiddenTest, config: &TestConfig) -> TestResult {
+    let routes = multi_routes(test.senders, test.receivers);
+    let mut sys = build_multi_system(config, test.senders, test.receivers);
+    sys.network().set_drop_rate(test.drop_rate);
+    sys.network().set_dupl_rate(test.dupl_rate);
+    sys.network().set_corrupt_rate(test.corrupt_rate);
+    if let Some(DelayDistribution::Uniform { min, max }) = test.delays {
+        sys.network().set_delays(min, max);
+    }
+    let sent = send_multi_messages(&mut sys, &routes, test.message_count);
+    // settling is tracked by the deliveries of a single receiver, so the run goes on until there are no events
+    step_until_no_events(&mut sys, config.max_steps)?;
+    check_multi_guarantees(&mut sys, &routes, &sent, config)?;
+    if let Some(max_sent) = test.max_sent {
+        let sent_count: u64 = routes.iter().map(|(sender, _)| sys.sent_message_count(sender)).sum();
+        assume!(
+            sent_count <= max_sent,
+            format!("Senders sent {} messages, expected at most {}", sent_count, max_sent)
+        )?;
+    }
+    Ok(true)
+}
+
+pub fn test_memory_pressure(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, true);
+    let (sender_mem_limit, receiver_mem_limit, ..) = overhead_limits(config.guarantee, false, 1000);
+    let mut sender_limit = MemoryLimit::new("sender", sender_mem_limit);
+    let mut receiver_limit = MemoryLimit::new("receiver", receiver_mem_limit);
+    let mut sender_history = SizeHistory::new("sender");
+    let mut receiver_history = SizeHistory::new("receiver");
+    let mut enforce = |sys: &mut System| {
+        sender_limit.enforce(sys);
+        receiver_limit.enforce(sys);
+        sender_history.record(sys);
+        receiver_history.record(sys);
+    };
+    let messages = send_messages_with(&mut sys, 1000, &mut enforce);
+    step_until_no_events_with(&mut sys, config.max_steps, &mut enforce)?;
+    println!("Sender memory over time: {:?}", sender_history.samples());
+    println!("Receiver memory over time: {:?}", receiver_history.samples());
+    sender_limit.check()?;
+    receiver_limit.check()?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+/// Longest time the ordered receiver may hold back the deliveries after the loss of the first message.
+const MAX_DELIVERY_STALL: f64 = 20.;
+
+pub fn test_head_of_line_stall(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    // the loss of the first message blocks the delivery of all the following ones until it is retransmitted
+    let drop = DropNth::new(&mut sys, 0);
+    let messages = send_messages_with(&mut sys, 10, |sys| drop.apply(sys));
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| drop.apply(sys))?;
+    let profile = outbox_growth_profile(&sys, "receiver");
+    let max_stall = profile
+        .iter()
+        .scan(0., |last_time, &(time, _)| {
+            Some(time - std::mem::replace(last_time, time))
+        })
+        .fold(0., f64::max);
+    println!("Receiver outbox growth: {profile:?}, max stall {max_stall:.3}");
+    check_max_stall(&profile, 0., messages.len(), MAX_DELIVERY_STALL)?;
+    check_delivery(&sys.read_local_messages("receiver"), &messages, config)
+}
+
+/// Most copies of a message from the sender that may travel the network at once without losses,
+/// one retransmission before the acknowledgement returns is fine with the longest delays.
+const MAX_IN_FLIGHT_COPIES: usize = 3;
+
+pub fn test_in_flight_copies(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    sys.network().set_delays(1., 3.);
+    // (time, copies, message data) of the largest number of copies in flight
+    let mut max_copies = (0., 0, String::new());
+    let mut track = |sys: &mut System| {
+        let mut copies: HashMap<String, usize> = HashMap::new();
+        for (src, _, msg, _) in in_flight_messages(sys) {
+            if src == "sender" {
+                *copies.entry(msg.data).or_default() += 1;
+            }
+        }
+        if let Some((data, count)) = copies.into_iter().max_by_key(|(_, count)| *count) {
+            if count > max_copies.1 {
+                max_copies = (sys.time(), count, data);
+            }
+        }
+    };
+    let messages = send_messages_with(&mut sys, 20, &mut track);
+    step_until_no_events_with(&mut sys, config.max_steps, &mut track)?;
+    let (time, count, data) = max_copies;
+    println!("Sender had at most {count} copies of a message in flight");
+    assume!(
+        count <= MAX_IN_FLIGHT_COPIES,
+        format!(
+            "Sender had {count} copies of message {data} in flight at time {time:.3}, more than {MAX_IN_FLIGHT_COPIES}"
+        )
+    )?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+/// Most timers the sender may have scheduled at once, a timer per message in flight is fine for a window of that size.
+const MAX_PENDING_TIMERS: usize = 20;
+
+pub fn test_pending_timers(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    sys.network().set_delays(1., 3.);
+    sys.network().set_drop_rate(0.1);
+    let messages = send_messages(&mut sys, 100);
+    let mut max_pending = 0;
+    for _ in 0..config.max_steps {
+        let pending = pending_timers(&sys, "sender");
+        max_pending = max_pending.max(pending.len());
+        assume!(
+            pending.len() <= MAX_PENDING_TIMERS,
+            format!(
+                "Sender has {} timers scheduled at time {:.3}, more than {MAX_PENDING_TIMERS}: {:?}",
+                pending.len(),
+                sys.time(),
+                &pending[..MAX_PENDING_TIMERS]
+            )
+        )?;
+        if !sys.step_for_duration(1.) {
+            break;
+        }
+    }
+    println!("Sender had at most {max_pending} timers scheduled");
+    check_guarantees(&mut sys, &messages, config)
+}
+
+pub fn test_limited_bandwidth(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    // a message with 100 characters of text takes about a unit of time to transmit,
+    // so redundant retransmissions and acknowledgements queue up and delay the useful messages
+    let mut links = LinkNetwork::new(&mut sys, (1., 1.));
+    links.set_bandwidth(150.);
+    let messages = send_messages_with_hooks(
+        &mut sys,
+        50,
+        |sys| links.apply_from(sys, "sender-node"),
+        |sys| links.apply(sys),
+    );
+    step_until_no_events_with(&mut sys, config.max_steps, |sys| links.apply(sys))?;
+    let delivered = sys.read_local_messages("receiver");
+    let last_delivery = first_and_last_delivery_time(&sys, "receiver").map_or(0., |(_, last)| last);
+    let net_message_count = sys.network().network_message_count();
+    let net_traffic = sys.network().traffic();
+    println!(
+        "Net messages: {net_message_count}, traffic: {net_traffic}, last delivery: {last_delivery:.3}, goodput: {:.3}",
+        goodput(&delivered, &messages, sys.time())
+    );
+    check_delivery(&delivered, &messages, config)
+}
+
+/// Simulated size of the message texts in the large messages test.
+const LARGE_MESSAGE_SIZE: usize = 10000;
+
+pub fn test_large_messages(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    let mut hints = SizeHints::new();
+    let messages: Vec<Message> = generate_message_texts(&mut sys, 50)
+        .iter()
+        .map(|text| hints.text_message(text, LARGE_MESSAGE_SIZE))
+        .collect();
+    send_local_messages(&mut sys, "sender", &messages);
+    step_until_settled(&mut sys, config)?;
+    let payload = hints.payload();
+    let sender_traffic = hints.traffic_from(&sys, "sender");
+    let receiver_traffic = hints.traffic_from(&sys, "receiver");
+    println!("Payload: {payload}, Send/Recv Traffic: {sender_traffic}/{receiver_traffic}");
+    // large messages are costly to retransmit, so without losses each of them should be sent about once
+    assume!(
+        sender_traffic <= 2 * payload,
+        format!("Sender traffic {sender_traffic} is more than twice the payload {payload}")
+    )?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
+pub fn test_selective_retransmit(config: &TestConfig) -> TestResult {
+    let texts = generate_message_texts(&mut build_system(config, false), 30);
+    // a single loss of a message creates a gap in the sequence of messages
+    let lost = 2;
+    let mut drop = DropMatching::new(|msg: &Message| msg.data.contains(&texts[lost]), 1);
+    let (mut sys, messages, _) = run_with_drop_matching(config, 30, &mut drop)?;
+    let sent = network_messages_sent_by(&sys, "sender");
+    let retransmitted: Vec<usize> = (0..texts.len())
+        .filter(|i| sent.iter().filter(|msg| msg.data.contains(&texts[*i])).count() > 1)
+        .collect();
+    let nak_count = network_messages_sent_by(&sys, "receiver")
+        .iter()
+        .filter(|msg| msg.tip.to_uppercase().contains("NAK"))
+        .count();
+    println!("Receiver sent {nak_count} NAKs, sender retransmitted messages {retransmitted:?}");
+    assume!(
+        retransmitted.iter().all(|i| *i == lost),
+        format!(
+            "Sender retransmitted messages {:?}, while only message {} was lost",
+            retransmitted, lost
+        )
+    )?;
+    check_guarantees(&mut sys, &messages, config)
+}
+
 pub fn test_overhead(config: &TestConfig, guarantee: &str, faulty: bool) -> TestResult {
+    let metric = config.overhead_metric;
     for message_count in [100, 500, 1000] {
-        let mut sys = build_system(config, true);
+        // measuring memory is costly, so it is skipped when not needed
+        let mut sys = build_system(config, metric.includes(OverheadMetric::Memory));
         if faulty {
             sys.network().set_delays(1., 3.);
             sys.network().set_dupl_rate(0.3);
             sys.network().set_drop_rate(0.3);
         }
         let messages = send_messages(&mut sys, message_count);
-        sys.step_until_no_events();
-        let res = check_guarantees(&mut sys, &messages, config);
-        res.as_ref()?;
+        step_until_settled(&mut sys, config)?;
+        let delivered = sys.read_local_messages("receiver");
+        check_delivery(&delivered, &messages, config)?;
         let sender_mem = sys.max_size("sender");
         let receiver_mem = sys.max_size("receiver");
         let net_message_count = sys.network().network_message_count();
         let net_traffic = sys.network().traffic();
-        let throughput = message_count as f64 / sys.time();
-        println!(
-            "{message_count:<6} Send Mem: {sender_mem:<8} Recv Mem: {receiver_mem:<8} Messages: {net_message_count:<8} Traffic: {net_traffic:<8} Throughput: {throughput:.3}"
-        );
+        let net_bytes = NetworkBytes::collect(&sys);
+        net_bytes.check(net_traffic)?;
+        let sender_traffic = traffic_from(&sys, "sender");
+        let receiver_traffic = traffic_from(&sys, "receiver");
+        assume_eq!(
+            message_count_from(&sys, "sender") + message_count_from(&sys, "receiver"),
+            net_message_count,
+            "Messages sent by processes do not match network message count"
+        )?;
+        let (first_delivery, last_delivery) = first_and_last_delivery_time(&sys, "receiver").unwrap_or_default();
+        let cold_throughput = message_count as f64 / sys.time();
+        let throughput = warm_throughput(&sys, message_count, config.overhead_warmup);
+        let goodput = goodput(&delivered, &messages, sys.time());
+        let min_messages = predict_min_messages(guarantee, message_count, sys.network().drop_rate());
+        let efficiency = net_message_count as f64 / min_messages;
+        let mut report = format!("{message_count:<6}");
+        if metric.includes(OverheadMetric::Memory) {
+            report.push_str(&format!(" Send Mem: {sender_mem:<8} Recv Mem: {receiver_mem:<8}"));
+        }
+        if metric.includes(OverheadMetric::Traffic) {
+            report.push_str(&format!(
+                " Messages: {net_message_count:<8} Traffic: {net_traffic:<8} Delivered: {:<8} ({:.2})",
+                net_bytes.delivered,
+                net_bytes.delivery_ratio()
+            ));
+            report.push_str(&format!(" Send/Recv Traffic: {sender_traffic}/{receiver_traffic}"));
+        }
+        if metric.includes(OverheadMetric::Throughput) {
+            report.push_str(&format!(" Throughput: {throughput:.3}"));
+            if config.overhead_warmup > 0 {
+                report.push_str(&format!(" (cold {cold_throughput:.3})"));
+            }
+            report.push_str(&format!("  Goodput: {goodput:.3}"));
+        }
+        if metric.includes(OverheadMetric::Traffic) {
+            report.push_str(&format!("  Messages/Optimal: {efficiency:.2}"));
+        }
+        if metric.includes(OverheadMetric::Throughput) {
+            report.push_str(&format!(
+                "  First/Last Delivery: {first_delivery:.3}/{last_delivery:.3}"
+            ));
+        }
+        println!("{}", report.trim_end());
+        record_overhead(OverheadRow {
+            guarantee: guarantee.to_string(),
+            faulty,
+            message_count,
+            sender_mem,
+            receiver_mem,
+            net_message_count,
+            net_traffic,
+            net_bytes_delivered: net_bytes.delivered,
+            throughput,
+            goodput,
+        });
         check_overhead(
             guarantee,
             faulty,
@@ -116,12 +1006,9 @@ pub fn test_overhead(config: &TestConfig, guarantee: &str, faulty: bool) -> Test
             net_message_count,
             net_traffic,
             throughput,
+            goodput,
+            metric,
         )?;
     }
-    let impl_code = fs::read_to_string(config.impl_path).unwrap();
-    assume!(
-        !impl_code.contains("<<") && !impl_code.contains(">>"),
-        "Implementation contains bitwise shift operators"
-    )?;
-    Ok(true)
+    config.source_check.check(config.impl_path)
 }
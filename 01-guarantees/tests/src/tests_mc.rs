@@ -1,29 +1,59 @@
-use std::collections::HashMap;
-use std::time::Duration;
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt::{self, Write};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
+use rand::prelude::*;
+use rand_pcg::Pcg64;
 use sugars::boxed;
 
 use anysystem::logger::LogEntry;
 use anysystem::mc::{
-    predicates::{goals, invariants, prunes},
-    strategies::Bfs,
-    InvariantFn, ModelChecker, StrategyConfig,
+    strategies::{Bfs, Dfs},
+    GoalFn, InvariantFn, McError, McResult, McState, McStats, McSystem, ModelChecker, PruneFn, Strategy,
+    StrategyConfig,
 };
 use anysystem::test::TestResult;
-use anysystem::Message;
+use anysystem::{Message, System};
 
 use crate::common::{
     build_system, check_delivered_messages, check_message_delivery_once, check_message_delivery_ordered,
-    check_message_delivery_reliable, generate_message_texts, TestConfig,
+    check_message_delivery_reliable, generate_message_texts, text_message, MessageFields, TestConfig,
 };
+use crate::predicates::{goals, invariants, prunes};
 
-fn mc_invariant_guarantees(messages_expected: Vec<Message>, config: TestConfig) -> InvariantFn {
+/// Names of the model checking strategies used by the tests.
+pub const MC_STRATEGIES: [&str; 5] = ["Bfs", "Dfs", "RandomWalk", "BestFirst", "Iddfs"];
+
+/// Builds an invariant over the expected messages.
+type InvariantBuilder = fn(Vec<Message>) -> InvariantFn;
+
+/// Guarantee-specific invariants checked in addition to the common ones.
+/// A new guarantee registers its invariants here.
+fn guarantee_invariants(guarantee: &str) -> Vec<InvariantBuilder> {
+    match guarantee {
+        "EOO" => vec![mc_invariant_ordered],
+        _ => vec![],
+    }
+}
+
+fn delivered_messages(state: &McState) -> &Vec<Message> {
+    &state.node_states["receiver-node"].proc_states["receiver"].local_outbox
+}
+
+fn mc_invariant_common(messages_expected: Vec<Message>, config: TestConfig) -> InvariantFn {
     boxed!(move |state| {
         let mut expected_msg_count = HashMap::new();
         for msg in &messages_expected {
             *expected_msg_count.entry(msg.data.clone()).or_insert(0) += 1;
         }
-        let delivered = &state.node_states["receiver-node"].proc_states["receiver"].local_outbox;
+        let delivered = delivered_messages(state);
 
         // check that delivered messages have expected type and data
         let delivered_msg_count = check_delivered_messages(delivered, &expected_msg_count, &messages_expected[0].tip)?;
@@ -35,66 +65,1023 @@ fn mc_invariant_guarantees(messages_expected: Vec<Message>, config: TestConfig)
         if config.once {
             check_message_delivery_once(&delivered_msg_count, &expected_msg_count)?;
         }
-        if config.ordered {
-            check_message_delivery_ordered(delivered, &messages_expected)?;
+        Ok(())
+    })
+}
+
+fn mc_invariant_ordered(messages_expected: Vec<Message>) -> InvariantFn {
+    boxed!(move |state| {
+        check_message_delivery_ordered(delivered_messages(state), &messages_expected)?;
+        Ok(())
+    })
+}
+
+fn mc_invariant_guarantees(messages_expected: Vec<Message>, config: TestConfig) -> InvariantFn {
+    let mut rules = vec![mc_invariant_common(messages_expected.clone(), config)];
+    for build in guarantee_invariants(config.guarantee) {
+        rules.push(build(messages_expected.clone()));
+    }
+    with_trigger(invariants::all_invariants(rules))
+}
+
+/// Extends the error of the invariant with the event which led to the violating state,
+/// e.g. "... after receiving MESSAGE {"text": "distributed"} from sender at receiver".
+fn with_trigger(mut invariant: InvariantFn) -> InvariantFn {
+    boxed!(move |state: &McState| {
+        invariant(state).map_err(|e| {
+            let trigger = state.current_run_trace().iter().rfind(|entry| is_transition(entry));
+            match trigger {
+                Some(entry) => format!("{e} after {}", trigger_description(entry)),
+                None => format!("{e} in the initial state"),
+            }
+        })
+    })
+}
+
+fn trigger_description(entry: &LogEntry) -> String {
+    match entry {
+        LogEntry::McMessageReceived { msg, src, dst } => {
+            format!("receiving {} {} from {src} at {dst}", msg.tip, msg.data)
+        }
+        LogEntry::McMessageDropped { msg, src, dst } => {
+            format!("dropping {} {} from {src} to {dst}", msg.tip, msg.data)
+        }
+        LogEntry::McMessageCorrupted { msg, src, dst, .. } => {
+            format!("corrupting {} {} from {src} to {dst}", msg.tip, msg.data)
+        }
+        LogEntry::McMessageDuplicated { msg, src, dst } => {
+            format!("duplicating {} {} from {src} to {dst}", msg.tip, msg.data)
+        }
+        LogEntry::McTimerFired { proc, .. } => format!("firing a timer at {proc}"),
+        _ => unreachable!(),
+    }
+}
+
+/// Evaluates the invariant only for every `n`-th explored state. The states where the search stops
+/// (states without events and states matching `stop`, i.e. the goal or prune ones) are always checked.
+///
+/// This speeds up large searches at the cost of promptness: a violation is detected in the next checked state,
+/// so the reported counterexample may be slightly longer than the shortest one.
+fn check_invariant_every(n: u32, mut invariant: InvariantFn, mut stop: GoalFn) -> InvariantFn {
+    if n <= 1 {
+        return invariant;
+    }
+    let mut state_count = 0;
+    boxed!(move |state| {
+        state_count += 1;
+        if state_count % n == 0 || state.events.is_empty() || stop(state).is_some() {
+            invariant(state)
+        } else {
+            Ok(())
+        }
+    })
+}
+
+/// Prefix of the error returned by a model checking test stopped by the wall-clock limit.
+pub const INCONCLUSIVE: &str = "Inconclusive";
+
+/// Stops the search once the given real time has passed by pruning all remaining states.
+///
+/// Such a search is inconclusive rather than successful, since the unexplored states might violate the invariants,
+/// so it is reported as an error starting with [`INCONCLUSIVE`].
+struct WallClockLimit {
+    limit: Option<Duration>,
+    start_time: Instant,
+    reached: Rc<Cell<bool>>,
+}
+
+impl WallClockLimit {
+    /// Creates the limit, the time is counted from this call.
+    fn new(limit: Option<f64>) -> Self {
+        Self {
+            limit: limit.map(Duration::from_secs_f64),
+            start_time: Instant::now(),
+            reached: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Extends the prune with the limit.
+    fn prune(&self, mut prune: PruneFn) -> PruneFn {
+        let Some(limit) = self.limit else {
+            return prune;
+        };
+        let start_time = self.start_time;
+        let reached = self.reached.clone();
+        boxed!(move |state| {
+            if reached.get() || start_time.elapsed() > limit {
+                reached.set(true);
+                return Some(format!("wall-clock limit of {}s exceeded", limit.as_secs_f32()));
+            }
+            prune(state)
+        })
+    }
+
+    fn result(&self, res: McResult) -> TestResult {
+        match res {
+            Err(e) => {
+                e.print_trace();
+                Err(e.message())
+            }
+            Ok(_) if self.reached.get() => Err(format!(
+                "{INCONCLUSIVE}: search is stopped by wall-clock limit of {}s (see --mc-time-limit)",
+                self.limit.unwrap().as_secs_f32()
+            )),
+            Ok(_) => Ok(true),
+        }
+    }
+}
+
+/// Transition of the model checker reconstructed from the trace: the event processed by a process
+/// and the effects of its processing.
+struct Transition<'a> {
+    entry: &'a LogEntry,
+    effects: &'a [LogEntry],
+}
+
+impl Transition<'_> {
+    /// Process the event belongs to, message events belong to the destination.
+    fn proc(&self) -> &str {
+        match self.entry {
+            LogEntry::McMessageReceived { dst, .. }
+            | LogEntry::McMessageDropped { dst, .. }
+            | LogEntry::McMessageCorrupted { dst, .. }
+            | LogEntry::McMessageDuplicated { dst, .. } => dst,
+            LogEntry::McTimerFired { proc, .. } => proc,
+            _ => unreachable!(),
+        }
+    }
+
+    fn creates_events(&self) -> bool {
+        matches!(self.entry, LogEntry::McMessageDuplicated { .. })
+            || self
+                .effects
+                .iter()
+                .any(|e| matches!(e, LogEntry::McMessageSent { .. } | LogEntry::McTimerSet { .. }))
+    }
+
+    /// Checks whether the event of `other` could be created by this transition.
+    fn enables(&self, other: &Transition) -> bool {
+        let (LogEntry::McMessageReceived { msg, src, dst }
+        | LogEntry::McMessageDropped { msg, src, dst }
+        | LogEntry::McMessageCorrupted { msg, src, dst, .. }
+        | LogEntry::McMessageDuplicated { msg, src, dst }) = other.entry
+        else {
+            return false;
+        };
+        self.effects.iter().any(
+            |e| matches!(e, LogEntry::McMessageSent { msg: m, src: s, dst: d } if m == msg && s == src && d == dst),
+        )
+    }
+}
+
+fn is_transition(entry: &LogEntry) -> bool {
+    matches!(
+        entry,
+        LogEntry::McMessageReceived { .. }
+            | LogEntry::McMessageDropped { .. }
+            | LogEntry::McMessageCorrupted { .. }
+            | LogEntry::McMessageDuplicated { .. }
+            | LogEntry::McTimerFired { .. }
+    )
+}
+
+/// Returns the last two transitions of the current run in their order.
+fn last_two_transitions(trace: &[LogEntry]) -> Option<(Transition<'_>, Transition<'_>)> {
+    let last_pos = trace.iter().rposition(is_transition)?;
+    let prev_pos = trace[..last_pos].iter().rposition(is_transition)?;
+    let prev = Transition {
+        entry: &trace[prev_pos],
+        effects: &trace[prev_pos + 1..last_pos],
+    };
+    let last = Transition {
+        entry: &trace[last_pos],
+        effects: &trace[last_pos + 1..],
+    };
+    Some((prev, last))
+}
+
+/// Partial-order reduction: prunes the states reached by executing two independent transitions
+/// in the non-canonical order, so only one of the two interleavings is explored further.
+///
+/// Transitions of different processes are considered independent unless the second one processes a message
+/// sent by the first one. The canonical order executes the transition of the process with the smaller name first.
+/// This relies on the handlers not depending on `ctx.time()`, which differs between the interleavings.
+///
+/// Both interleavings lead to the same state up to the ids of the pending events. The reduction is applied only
+/// when both transitions create new events, since then the ids differ and the state cache cannot confuse
+/// the pruned state with the explored one.
+fn partial_order_reduction() -> PruneFn {
+    boxed!(|state: &McState| {
+        let (prev, last) = last_two_transitions(state.current_run_trace())?;
+        if last.proc() < prev.proc() && !prev.enables(&last) && prev.creates_events() && last.creates_events() {
+            Some("partial-order reduction".to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Maps a state to the hash of its canonical representative, equal for the states considered symmetric.
+type SymmetryFn = Box<dyn Fn(&McState) -> u64>;
+
+/// Considers the states symmetric if they differ only by a permutation of the texts of the expected messages.
+///
+/// The canonical form consists of the states and outboxes of the processes and the pending events regardless
+/// of their ids, with the texts relabeled in the order of their first appearance in the states of the processes.
+/// It also includes the kinds of the events happened in the current run, since the prunes limit their number.
+fn relabel_messages(messages: &[Message]) -> SymmetryFn {
+    let texts: Vec<String> = messages.iter().map(|msg| msg.get::<String>("text").unwrap()).collect();
+    boxed!(move |state: &McState| {
+        let procs: String = state
+            .node_states
+            .values()
+            .flat_map(|node| node.proc_states.iter())
+            .map(|(proc, entry)| format!("{proc}:{:?}:{:?};", entry.proc_state, entry.local_outbox))
+            .collect();
+        let mut labels: Vec<(usize, &String)> = texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| (procs.find(text.as_str()).unwrap_or(procs.len() + i), text))
+            .collect();
+        labels.sort();
+        let relabel = |mut repr: String| {
+            for (label, (_, text)) in labels.iter().enumerate() {
+                repr = repr.replace(text.as_str(), &format!("#{label}"));
+            }
+            repr
+        };
+        // every pending event is logged to the trace when created, so the ids do not exceed its length
+        let mut events: Vec<String> = (0..state.trace.len())
+            .filter_map(|id| state.events.get(id))
+            .map(|event| relabel(format!("{event:?}")))
+            .collect();
+        events.sort();
+        let mut kinds: Vec<_> = state.current_run_trace().iter().map(std::mem::discriminant).collect();
+        kinds.sort_by_key(|kind| format!("{kind:?}"));
+        let mut hasher = DefaultHasher::new();
+        relabel(procs).hash(&mut hasher);
+        events.hash(&mut hasher);
+        kinds.hash(&mut hasher);
+        hasher.finish()
+    })
+}
+
+/// Symmetry reduction: prunes the states symmetric to an already checked one.
+///
+/// The model checker keeps the original states, so the reduction is applied as a prune with its own cache
+/// of canonical representatives, and a counterexample trace is reported in the original labeling.
+fn symmetry_reduction(symmetry: SymmetryFn) -> PruneFn {
+    let mut canonical = HashSet::new();
+    boxed!(move |state: &McState| {
+        if canonical.insert(symmetry(state)) {
+            None
+        } else {
+            Some("symmetry reduction".to_string())
+        }
+    })
+}
+
+/// Extends the prune with the partial-order reduction if it is enabled by `--mc-por` and with the symmetry
+/// reduction over the message texts if it is enabled by `--mc-symmetry`.
+///
+/// The symmetry reduction is not applied to ordered guarantees, since the order of the messages is not symmetric.
+fn reduced(config: &TestConfig, messages: &[Message], prune: PruneFn) -> PruneFn {
+    let mut prunes = vec![prune];
+    if config.mc_por {
+        prunes.insert(0, partial_order_reduction());
+    }
+    if config.mc_symmetry && !config.ordered {
+        prunes.push(symmetry_reduction(relabel_messages(messages)));
+    }
+    if prunes.len() == 1 {
+        prunes.pop().unwrap()
+    } else {
+        prunes::any_prune(prunes)
+    }
+}
+
+/// Statistics of a model checking search.
+///
+/// The model checker reports only the collected states, so the statistics are gathered from the states
+/// checked by the invariant. Each check of a state other than the initial one follows a transition into it,
+/// e.g. a message delivery or drop, so the checks count the transitions, while the states are counted by their hashes.
+/// A state reached by several transitions, e.g. by different orders of deliveries in the runs of a random walk,
+/// iterative deepening or best-first search, is counted once. The transitions into the states the model checker
+/// skips as already visited are not checked, so they are not counted.
+#[derive(Default)]
+struct SearchStats {
+    /// Hashes of the distinct states explored.
+    states: HashSet<u64>,
+    max_depth: u64,
+    transitions: u64,
+    elapsed: Duration,
+}
+
+impl SearchStats {
+    /// Extends the invariant with counting of the checked states.
+    fn record(stats: Rc<RefCell<Self>>, mut invariant: InvariantFn) -> InvariantFn {
+        boxed!(move |state: &McState| {
+            let mut stats = stats.borrow_mut();
+            stats.states.insert(state_hash(state));
+            stats.max_depth = stats.max_depth.max(state.depth);
+            if state.depth > 0 {
+                stats.transitions += 1;
+            }
+            drop(stats);
+            invariant(state)
+        })
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Explored {} states up to depth {} over {} transitions in {:.2}s",
+            self.states.len(),
+            self.max_depth,
+            self.transitions,
+            self.elapsed.as_secs_f64()
+        )
+    }
+}
+
+/// Distinct invariant violations collected with `--mc-max-violations` instead of stopping the search at the first one.
+///
+/// The model checker stops at the first error of the invariant, so the violations are collected by the invariant,
+/// which reports success for them until `max` distinct violations are found. The violating states are explored
+/// further, so their successors may violate the invariants as well, only the violations with new errors are kept.
+struct Violations {
+    max: u32,
+    /// Errors and the traces leading to the violating states.
+    errors: Vec<(String, Vec<LogEntry>)>,
+}
+
+impl Violations {
+    fn new(max: u32) -> Self {
+        Self {
+            max,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Extends the invariant with collecting of the violations.
+    fn record(violations: Rc<RefCell<Self>>, mut invariant: InvariantFn) -> InvariantFn {
+        boxed!(move |state: &McState| {
+            let Err(e) = invariant(state) else {
+                return Ok(());
+            };
+            let mut violations = violations.borrow_mut();
+            if !violations.errors.iter().any(|(known, _)| *known == e) {
+                violations.errors.push((e.clone(), state.trace.clone()));
+            }
+            if violations.errors.len() >= violations.max as usize {
+                Err(e)
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Returns the result of the search with the collected violations: the first one is returned as the error
+    /// and the others are printed along with their traces.
+    fn result(&self, res: McResult) -> McResult {
+        let Some((first, first_trace)) = self.errors.first() else {
+            return res;
+        };
+        for (i, (e, trace)) in self.errors.iter().enumerate().skip(1) {
+            println!("Violation {} of {}: {e}", i + 1, self.errors.len());
+            McError::new(e.clone(), trace.clone()).print_trace();
+        }
+        let message = match self.errors.len() {
+            1 => first.clone(),
+            n => format!("{first} (and {} more violations printed above)", n - 1),
+        };
+        Err(McError::new(message, first_trace.clone()))
+    }
+}
+
+/// Graph of the states explored by the model checker, saved in DOT format with `--mc-graph`.
+///
+/// The states are recorded when checked by the invariant, and each of them is linked to its predecessor, which is
+/// found by the trace leading to the state without its last transition. The model checker does not check the states
+/// it has already visited, so a transition into such a state from another path is not recorded.
+#[derive(Default)]
+struct StateGraph {
+    /// Ids of the nodes by the hashes of the states.
+    nodes: HashMap<u64, usize>,
+    /// Hashes of the states by the hashes of the traces leading to them.
+    traces: HashMap<u64, u64>,
+    /// Depth of each node.
+    depths: Vec<u64>,
+    /// Edges between the nodes labeled with the transitions.
+    edges: Vec<(usize, usize, String)>,
+    /// Node violating the invariant and the error.
+    violation: Option<(usize, String)>,
+}
+
+fn trace_hash(trace: &[LogEntry]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{trace:?}").hash(&mut hasher);
+    hasher.finish()
+}
+
+fn transition_label(entry: &LogEntry) -> String {
+    match entry {
+        LogEntry::McMessageReceived { msg, src, dst } => format!("{} {src} -> {dst}", msg.tip),
+        LogEntry::McMessageDropped { msg, src, dst } => format!("drop {} {src} -> {dst}", msg.tip),
+        LogEntry::McMessageCorrupted { msg, src, dst, .. } => format!("corrupt {} {src} -> {dst}", msg.tip),
+        LogEntry::McMessageDuplicated { msg, src, dst } => format!("duplicate {} {src} -> {dst}", msg.tip),
+        LogEntry::McTimerFired { proc, .. } => format!("timer at {proc}"),
+        _ => unreachable!(),
+    }
+}
+
+impl StateGraph {
+    fn add_state(&mut self, state: &McState) -> usize {
+        let hash = state_hash(state);
+        self.traces.insert(trace_hash(&state.trace), hash);
+        let next_id = self.depths.len();
+        let id = *self.nodes.entry(hash).or_insert(next_id);
+        if id == next_id {
+            self.depths.push(state.depth);
+        }
+        if let Some(pos) = state.trace.iter().rposition(is_transition) {
+            let parent = self
+                .traces
+                .get(&trace_hash(&state.trace[..pos]))
+                .map(|hash| self.nodes[hash]);
+            if let Some(parent) = parent {
+                self.edges.push((parent, id, transition_label(&state.trace[pos])));
+            }
+        }
+        id
+    }
+
+    /// Extends the invariant with recording of the checked states.
+    fn record(graph: Rc<RefCell<Self>>, mut invariant: InvariantFn) -> InvariantFn {
+        boxed!(move |state: &McState| {
+            let id = graph.borrow_mut().add_state(state);
+            let res = invariant(state);
+            if let Err(e) = &res {
+                graph.borrow_mut().violation.get_or_insert((id, e.clone()));
+            }
+            res
+        })
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = "digraph states {\n".to_string();
+        for (id, depth) in self.depths.iter().enumerate() {
+            match &self.violation {
+                Some((violated, error)) if *violated == id => {
+                    let label = format!("{id}: {error}");
+                    writeln!(dot, "  s{id} [label={label:?}, color=red, style=filled];").unwrap();
+                }
+                _ => writeln!(dot, "  s{id} [label=\"{id} (depth {depth})\"];").unwrap(),
+            }
+        }
+        for (from, to, label) in &self.edges {
+            writeln!(dot, "  s{from} -> s{to} [label={label:?}];").unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Extension of [`ModelChecker`] for the changes of the initial state that can fail, e.g. when they depend on data
+/// that might be missing. A failed change is reported as an error of the search instead of a panic.
+trait RunWithFallibleChange {
+    fn run_with_change_fallible<S: Strategy>(
+        &mut self,
+        strategy_config: StrategyConfig,
+        change: impl Fn(&mut McSystem) -> Result<(), String>,
+    ) -> McResult;
+}
+
+impl RunWithFallibleChange for ModelChecker {
+    /// Runs the search like [`ModelChecker::run_with_change`].
+    ///
+    /// The change cannot stop the model checker, so it is first applied in a search stopping in the initial state,
+    /// and the actual search is run only if the change succeeds there.
+    fn run_with_change_fallible<S: Strategy>(
+        &mut self,
+        strategy_config: StrategyConfig,
+        change: impl Fn(&mut McSystem) -> Result<(), String>,
+    ) -> McResult {
+        let error = RefCell::new(None);
+        let checked_change = |sys: &mut McSystem| {
+            if let Err(e) = change(sys) {
+                error.borrow_mut().get_or_insert(e);
+            }
+        };
+        self.run_with_change::<Dfs>(StrategyConfig::default().goal(goals::depth_reached(0)), &checked_change)?;
+        if let Some(e) = error.take() {
+            return Err(McError::new(
+                format!("failed to change the initial state: {e}"),
+                Vec::new(),
+            ));
+        }
+        self.run_with_change::<S>(strategy_config, checked_change)
+    }
+}
+
+/// Runs the search with the strategy selected by `--mc-strategy`.
+///
+/// The predicates are passed as constructors, since a random walk runs the model checker many times.
+/// DFS keeps only the current path instead of the whole frontier, so it needs much less memory on large searches,
+/// but the counterexample it finds is not necessarily the shortest one. The heuristic estimates the number of steps
+/// from a state to the goal and is used only by the best-first search.
+///
+/// The statistics of the search are printed after it, and with `--mc-graph` the explored states are also saved
+/// to the file in DOT format.
+fn run_search(
+    config: &TestConfig,
+    sys: &System,
+    prune: impl Fn() -> PruneFn,
+    goal: impl Fn() -> GoalFn,
+    invariant: impl Fn() -> InvariantFn,
+    heuristic: impl Fn(&McState) -> u64,
+    change: impl Fn(&mut McSystem) -> Result<(), String>,
+) -> McResult {
+    let stats = Rc::new(RefCell::new(SearchStats::default()));
+    let graph = config.mc_graph.map(|_| Rc::new(RefCell::new(StateGraph::default())));
+    let violations =
+        (config.mc_max_violations > 1).then(|| Rc::new(RefCell::new(Violations::new(config.mc_max_violations))));
+    let invariant = || {
+        let invariant = match &violations {
+            Some(violations) => Violations::record(violations.clone(), invariant()),
+            None => invariant(),
+        };
+        let invariant = SearchStats::record(stats.clone(), invariant);
+        match &graph {
+            Some(graph) => StateGraph::record(graph.clone(), invariant),
+            None => invariant,
+        }
+    };
+    let start_time = Instant::now();
+    let mut res = run_strategy(config, sys, prune, goal, invariant, heuristic, change);
+    stats.borrow_mut().elapsed = start_time.elapsed();
+    if let Some(violations) = violations {
+        res = violations.borrow().result(res);
+    }
+    println!("{}", stats.borrow());
+    if let (Some(path), Some(graph)) = (config.mc_graph, graph) {
+        let graph = graph.borrow();
+        match fs::write(path, graph.to_dot()) {
+            Ok(()) => println!("State graph with {} states is saved to {path}", graph.depths.len()),
+            Err(e) => eprintln!("Cannot write state graph to {path}: {e}"),
+        }
+    }
+    res
+}
+
+fn run_strategy(
+    config: &TestConfig,
+    sys: &System,
+    prune: impl Fn() -> PruneFn,
+    goal: impl Fn() -> GoalFn,
+    invariant: impl Fn() -> InvariantFn,
+    heuristic: impl Fn(&McState) -> u64,
+    change: impl Fn(&mut McSystem) -> Result<(), String>,
+) -> McResult {
+    let strategy_config = || StrategyConfig::default().prune(prune()).goal(goal());
+    let mut mc = ModelChecker::new(sys);
+    match (config.mc_strategy, config.mc_event_order) {
+        (_, EventOrder::Interleaved) => {}
+        (strategy @ ("Bfs" | "Dfs"), order) => {
+            let depth_first = strategy == "Dfs";
+            return run_event_ordered(&mut mc, order, depth_first, prune(), goal(), invariant(), change);
+        }
+        _ => {}
+    }
+    match config.mc_strategy {
+        "Dfs" => mc.run_with_change_fallible::<Dfs>(strategy_config().invariant(invariant()), change),
+        "RandomWalk" => run_random_walks(config, sys, prune, goal, invariant, change),
+        "Iddfs" => run_iddfs(config, &mut mc, prune, goal, invariant, change),
+        "BestFirst" => run_best_first(&mut mc, prune(), goal(), invariant(), heuristic, change),
+        _ => mc.run_with_change_fallible::<Bfs>(strategy_config().invariant(invariant()), change),
+    }
+}
+
+/// Progress of the random walks shared between the predicates of the model checker runs.
+#[derive(Default)]
+struct RandomWalk {
+    /// Indices of the successors chosen at each depth of the current walk.
+    path: Vec<usize>,
+    /// Number of the successors seen so far at each depth in the current run.
+    seen: Vec<usize>,
+    /// Whether the last checked state is off the current walk.
+    off_walk: bool,
+    /// Hashes of the states visited by all walks.
+    visited: HashSet<u64>,
+}
+
+/// Tracks the position of the checked state relative to the walk, must be evaluated first for every state.
+fn walk_invariant(walk: Rc<RefCell<RandomWalk>>) -> InvariantFn {
+    boxed!(move |state: &McState| {
+        let mut walk = walk.borrow_mut();
+        let depth = state.depth as usize;
+        if depth > 0 {
+            if walk.seen.len() < depth {
+                walk.seen.resize(depth, 0);
+            }
+            let index = walk.seen[depth - 1];
+            walk.seen[depth - 1] += 1;
+            walk.off_walk = walk.path.get(depth - 1) != Some(&index);
+        } else {
+            walk.off_walk = false;
+        }
+        if !walk.off_walk {
+            // the successors of the state on the walk are counted from scratch
+            walk.seen.truncate(depth);
+            let mut hasher = DefaultHasher::new();
+            state.hash(&mut hasher);
+            walk.visited.insert(hasher.finish());
         }
         Ok(())
     })
 }
 
-pub fn test_mc_reliable_network(config: &TestConfig) -> TestResult {
-    let mut sys = build_system(config, false);
-    let messages: Vec<Message> = generate_message_texts(&mut sys, 2)
+fn walk_prune(walk: Rc<RefCell<RandomWalk>>, mut prune: PruneFn) -> PruneFn {
+    boxed!(move |state| {
+        if walk.borrow().off_walk {
+            Some("off the random walk".to_string())
+        } else {
+            prune(state)
+        }
+    })
+}
+
+/// Explores `--mc-walks` random paths from the initial state instead of the whole state space.
+///
+/// Each walk repeatedly moves to a random successor of the current state until it reaches a goal,
+/// a pruned state or a state without events. The model checker API does not allow to step through the states directly,
+/// so the walk is emulated with DFS: the run follows the chosen path, prunes the states off it and counts
+/// the successors of its last state to choose the next step. The successors are explored in the same order
+/// in every run, so they are identified by their index.
+///
+/// The walks are seeded with `--mc-walk-seed` (the seed of the suite by default), independently of the system.
+fn run_random_walks(
+    config: &TestConfig,
+    sys: &System,
+    prune: impl Fn() -> PruneFn,
+    goal: impl Fn() -> GoalFn,
+    invariant: impl Fn() -> InvariantFn,
+    change: impl Fn(&mut McSystem) -> Result<(), String>,
+) -> McResult {
+    let mut rand = Pcg64::seed_from_u64(config.mc_walk_seed.unwrap_or(config.seed));
+    let walk = Rc::new(RefCell::new(RandomWalk::default()));
+    let mut stats = McStats::default();
+    for _ in 0..config.mc_walks {
+        walk.borrow_mut().path.clear();
+        loop {
+            walk.borrow_mut().seen.clear();
+            let strategy_config = StrategyConfig::default()
+                .prune(walk_prune(walk.clone(), prune()))
+                .goal(goal())
+                .invariant(invariants::all_invariants(vec![
+                    walk_invariant(walk.clone()),
+                    invariant(),
+                ]));
+            stats = ModelChecker::new(sys).run_with_change_fallible::<Dfs>(strategy_config, &change)?;
+            let mut walk = walk.borrow_mut();
+            let successors = walk.seen.get(walk.path.len()).copied().unwrap_or(0);
+            if successors == 0 {
+                break;
+            }
+            let next = rand.gen_range(0..successors);
+            walk.path.push(next);
+        }
+    }
+    println!(
+        "{} random walks visited {} distinct states",
+        config.mc_walks,
+        walk.borrow().visited.len()
+    );
+    Ok(stats)
+}
+
+/// Runs depth-limited DFS with the bound growing from `--mc-min-depth` to `--mc-max-depth` by one.
+///
+/// Each iteration starts from scratch with an empty cache of visited states, so the memory is bounded like in DFS,
+/// while the counterexample found is as short as with BFS at the cost of exploring the shallow states many times.
+/// The search stops once an iteration explores all states without reaching the bound. Otherwise the last iteration
+/// does not explore the states deeper than the maximum depth, which is also the limit of the invariants of the tests.
+fn run_iddfs(
+    config: &TestConfig,
+    mc: &mut ModelChecker,
+    prune: impl Fn() -> PruneFn,
+    goal: impl Fn() -> GoalFn,
+    invariant: impl Fn() -> InvariantFn,
+    change: impl Fn(&mut McSystem) -> Result<(), String>,
+) -> McResult {
+    let mut stats = McStats::default();
+    for bound in config.mc_min_depth..=config.mc_max_depth {
+        let bound_reached = Rc::new(Cell::new(false));
+        let depth_limit = {
+            let bound_reached = bound_reached.clone();
+            boxed!(move |state: &McState| {
+                let pruned = state.depth > bound;
+                bound_reached.set(bound_reached.get() || pruned);
+                pruned.then(|| format!("state depth exceeds the bound {bound} of the iteration"))
+            })
+        };
+        let strategy_config = StrategyConfig::default()
+            .prune(prunes::any_prune(vec![prune(), depth_limit]))
+            .goal(goal())
+            .invariant(invariant());
+        stats = mc.run_with_change_fallible::<Dfs>(strategy_config, &change)?;
+        if !bound_reached.get() {
+            println!("Iterative deepening completed at depth {bound}");
+            return Ok(stats);
+        }
+    }
+    println!("Iterative deepening stopped at maximum depth {}", config.mc_max_depth);
+    Ok(stats)
+}
+
+/// Estimates the number of steps to the goal as the number of expected messages not yet delivered by receiver.
+///
+/// Each delivery takes at least one step, so the estimate never exceeds the actual number of steps.
+fn undelivered_messages(message_count: usize) -> impl Fn(&McState) -> u64 {
+    move |state| message_count.saturating_sub(delivered_messages(state).len()) as u64
+}
+
+fn state_hash(state: &McState) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    state.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Explores the states in the order of the heuristic estimate, the state closest to the goal first.
+///
+/// The strategies of the model checker are built into it, so the search is emulated by [`run_expanding`].
+/// All states are remembered, so the search is exhaustive like BFS and DFS, and the heuristic affects only
+/// how soon an error is found.
+fn run_best_first(
+    mc: &mut ModelChecker,
+    prune: PruneFn,
+    goal: GoalFn,
+    invariant: InvariantFn,
+    heuristic: impl Fn(&McState) -> u64,
+    change: impl Fn(&mut McSystem) -> Result<(), String>,
+) -> McResult {
+    let (stats, expanded) = run_expanding(
+        mc,
+        prune,
+        goal,
+        invariant,
+        |state, queued| (heuristic(state), state.depth, queued),
+        change,
+    )?;
+    println!("Best-first search expanded {expanded} states");
+    Ok(stats)
+}
+
+/// Order in which the model checker tries the events enabled in a state, selected by `--mc-event-order`.
+///
+/// The order does not change the set of reachable states, only the order they are visited in, and so which
+/// counterexample is found first. With `Interleaved` the events are tried in the order of the model checker itself.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventOrder {
+    MessagesFirst,
+    TimersFirst,
+    Interleaved,
+}
+
+impl EventOrder {
+    pub const NAMES: [&'static str; 3] = ["MessagesFirst", "TimersFirst", "Interleaved"];
+
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "MessagesFirst" => Self::MessagesFirst,
+            "TimersFirst" => Self::TimersFirst,
+            _ => Self::Interleaved,
+        }
+    }
+
+    /// Returns the rank of the state by the event leading to it, states with smaller ranks are explored first.
+    fn rank(self, state: &McState) -> u8 {
+        let timer = matches!(
+            state.current_run_trace().iter().rfind(|entry| is_transition(entry)),
+            Some(LogEntry::McTimerFired { .. })
+        );
+        match self {
+            Self::TimersFirst => u8::from(!timer),
+            _ => u8::from(timer),
+        }
+    }
+}
+
+/// Runs BFS (or DFS with `depth_first`) trying the events in the given order, see [`run_expanding`].
+fn run_event_ordered(
+    mc: &mut ModelChecker,
+    order: EventOrder,
+    depth_first: bool,
+    prune: PruneFn,
+    goal: GoalFn,
+    invariant: InvariantFn,
+    change: impl Fn(&mut McSystem) -> Result<(), String>,
+) -> McResult {
+    // DFS continues from the deepest and the most recently queued state
+    let key = |state: &McState, queued: usize| {
+        if depth_first {
+            (u64::MAX - state.depth, order.rank(state), usize::MAX - queued)
+        } else {
+            (state.depth, order.rank(state), queued)
+        }
+    };
+    let (stats, _) = run_expanding(mc, prune, goal, invariant, key, change)?;
+    Ok(stats)
+}
+
+/// Explores the states in the order of the key, the state with the smallest key first, and returns the statistics
+/// of the last run of the model checker along with the number of expanded states. The key is computed for a state
+/// and the number of states queued before it.
+///
+/// The search is emulated by expanding one state at a time: the model checker is run from the state with DFS stopping
+/// at its successors, which are collected and added to the queue unless they reach the goal or are pruned.
+/// The states are checked by the invariant once, when they are reached. The partial-order reduction has no effect,
+/// as each run makes a single step.
+fn run_expanding<K: Ord>(
+    mc: &mut ModelChecker,
+    mut prune: PruneFn,
+    mut goal: GoalFn,
+    invariant: InvariantFn,
+    key: impl Fn(&McState, usize) -> K,
+    change: impl Fn(&mut McSystem) -> Result<(), String>,
+) -> Result<(McStats, u64), McError> {
+    let invariant = Rc::new(RefCell::new(invariant));
+    let root_depth = Rc::new(Cell::new(0));
+    let strategy_config = |check_root: bool| {
+        let invariant = invariant.clone();
+        let (invariant_depth, goal_depth, collect_depth) = (root_depth.clone(), root_depth.clone(), root_depth.clone());
+        StrategyConfig::default()
+            .invariant(boxed!(move |state: &McState| {
+                if check_root || state.depth > invariant_depth.get() {
+                    (invariant.borrow_mut())(state)?;
+                }
+                Ok(())
+            }))
+            .goal(boxed!(
+                move |state: &McState| (state.depth > goal_depth.get()).then(|| "expanded".to_string())
+            ))
+            .prune(boxed!(|_: &McState| None))
+            .collect(boxed!(move |state: &McState| state.depth > collect_depth.get()))
+    };
+    // states are identified by the order they are queued
+    let mut queue = BinaryHeap::new();
+    let mut states = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut stats = mc.run_with_change_fallible::<Dfs>(strategy_config(true), change)?;
+    let (mut queued, mut expanded) = (0, 0);
+    loop {
+        let mut successors: Vec<McState> = mem::take(&mut stats.collected_states).into_iter().collect();
+        successors.sort_by_cached_key(state_hash);
+        for state in successors {
+            if !visited.insert(state_hash(&state)) || goal(&state).is_some() || prune(&state).is_some() {
+                continue;
+            }
+            if state.events.is_empty() {
+                return Err(McError::new(
+                    "nothing left to do to reach the goal".to_string(),
+                    state.trace.clone(),
+                ));
+            }
+            queue.push(Reverse((key(&state, queued), queued)));
+            states.insert(queued, state);
+            queued += 1;
+        }
+        let Some(Reverse((_, index))) = queue.pop() else {
+            break;
+        };
+        let state = states.remove(&index).unwrap();
+        root_depth.set(state.depth);
+        stats = mc.run_from_states::<Dfs>(strategy_config(false), HashSet::from([state]))?;
+        expanded += 1;
+    }
+    Ok((stats, expanded))
+}
+
+/// Snapshot of a running system: process states, messages in flight and pending timers.
+///
+/// The snapshot is taken as a model checking state, and the run can be continued from it only by the model checker,
+/// which explores all runs from this point on. It cannot be restored into a [`System`], since the simulation
+/// provides no way to replace its pending events and timers. Snapshots can be cloned to keep several checkpoints
+/// of a run and check each of them several times, e.g. with and without failures of the network.
+#[derive(Clone)]
+pub struct SystemSnapshot {
+    state: McState,
+}
+
+impl SystemSnapshot {
+    pub fn take(sys: &System) -> Self {
+        // the initial state of the model checker is collected and the search stops right there
+        let strategy_config = StrategyConfig::default()
+            .collect(boxed!(|_: &McState| true))
+            .goal(goals::depth_reached(0));
+        let stats = ModelChecker::new(sys).run::<Dfs>(strategy_config).unwrap();
+        Self {
+            state: stats.collected_states.into_iter().next().unwrap(),
+        }
+    }
+
+    /// Runs model checking from the snapshot after applying the callback.
+    ///
+    /// The system is used only to set up the model checker and must have the same processes as the snapshotted one,
+    /// the snapshotted system itself will do.
+    pub fn model_check<S: Strategy>(
+        &self,
+        sys: &System,
+        strategy_config: StrategyConfig,
+        change: impl Fn(&mut McSystem),
+    ) -> McResult {
+        ModelChecker::new(sys).run_from_states_with_change::<S>(
+            strategy_config,
+            HashSet::from([self.state.clone()]),
+            change,
+        )
+    }
+}
+
+fn mc_messages(sys: &mut System, count: usize) -> Vec<Message> {
+    generate_message_texts(sys, count)
         .into_iter()
-        .map(|text| Message::new("MESSAGE", &format!(r#"{{"text": "{text}"}}"#)))
-        .collect();
-    let strategy_config = StrategyConfig::default()
-        .prune(prunes::sent_messages_limit(4))
-        .goal(goals::got_n_local_messages("receiver-node", "receiver", 2))
-        .invariant(invariants::all_invariants(vec![
-            invariants::state_depth(20),
-            mc_invariant_guarantees(messages.clone(), *config),
-        ]));
-    let mut mc = ModelChecker::new(&sys);
-    let res = mc.run_with_change::<Bfs>(strategy_config, move |sys| {
+        .map(|text| text_message(&text))
+        .collect()
+}
+
+/// Sends the messages from the user to the sender at the start of the search.
+///
+/// The invariants compare the delivered messages with the first expected one, so there must be some.
+fn send_mc_messages(messages: &[Message]) -> impl Fn(&mut McSystem) -> Result<(), String> + '_ {
+    move |sys| {
+        if messages.is_empty() {
+            return Err("no messages to send".to_string());
+        }
         for message in messages {
-            sys.send_local_message("sender-node", "sender", message);
+            sys.send_local_message("sender-node", "sender", message.clone());
         }
-    });
-    if let Err(e) = res {
-        e.print_trace();
-        Err(e.message())
-    } else {
-        Ok(true)
+        Ok(())
     }
 }
 
+pub fn test_mc_reliable_network(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    let messages = mc_messages(&mut sys, 2);
+    let prune = || reduced(config, &messages, prunes::sent_messages_limit(4));
+    let goal = || goals::got_n_local_messages("receiver-node", "receiver", 2);
+    let wall_clock = WallClockLimit::new(config.mc_time_limit);
+    let res = run_search(
+        config,
+        &sys,
+        || wall_clock.prune(prune()),
+        goal,
+        || {
+            invariants::all_invariants(vec![
+                invariants::state_depth(20),
+                check_invariant_every(
+                    config.mc_check_every,
+                    mc_invariant_guarantees(messages.clone(), *config),
+                    goals::any_goal(vec![goal(), prune()]),
+                ),
+            ])
+        },
+        undelivered_messages(messages.len()),
+        send_mc_messages(&messages),
+    );
+    wall_clock.result(res)
+}
+
 pub fn test_mc_message_drops(config: &TestConfig) -> TestResult {
     let mut sys = build_system(config, false);
     sys.network().set_drop_rate(0.1);
-    let messages: Vec<Message> = generate_message_texts(&mut sys, 2)
-        .into_iter()
-        .map(|text| Message::new("MESSAGE", &format!(r#"{{"text": "{text}"}}"#)))
-        .collect();
-    let strategy_config = StrategyConfig::default()
-        .prune(prunes::state_depth(7))
-        .goal(goals::any_goal(vec![
+    let messages = mc_messages(&mut sys, 2);
+    let prune = || reduced(config, &messages, prunes::state_depth(7));
+    let goal = || {
+        goals::any_goal(vec![
             goals::got_n_local_messages("receiver-node", "receiver", 2),
             goals::no_events(),
-        ]))
-        .invariant(mc_invariant_guarantees(messages.clone(), *config));
-    let mut mc = ModelChecker::new(&sys);
-    let res = mc.run_with_change::<Bfs>(strategy_config, move |sys| {
-        for message in messages {
-            sys.send_local_message("sender-node", "sender", message);
-        }
-    });
-    if let Err(e) = res {
-        e.print_trace();
-        Err(e.message())
-    } else {
-        Ok(true)
-    }
+        ])
+    };
+    let wall_clock = WallClockLimit::new(config.mc_time_limit);
+    let res = run_search(
+        config,
+        &sys,
+        || wall_clock.prune(prune()),
+        goal,
+        || {
+            check_invariant_every(
+                config.mc_check_every,
+                mc_invariant_guarantees(messages.clone(), *config),
+                goals::any_goal(vec![goal(), prune()]),
+            )
+        },
+        undelivered_messages(messages.len()),
+        send_mc_messages(&messages),
+    );
+    wall_clock.result(res)
 }
 
 pub fn test_mc_unstable_network(config: &TestConfig) -> TestResult {
@@ -102,47 +1089,124 @@ pub fn test_mc_unstable_network(config: &TestConfig) -> TestResult {
     sys.network().set_drop_rate(0.1);
     sys.network().set_dupl_rate(0.1);
     let msg_count = if config.ordered { 3 } else { 2 };
-    let messages: Vec<Message> = generate_message_texts(&mut sys, msg_count)
-        .into_iter()
-        .map(|text| Message::new("MESSAGE", &format!(r#"{{"text": "{text}"}}"#)))
-        .collect();
+    let messages = mc_messages(&mut sys, msg_count);
     let num_drops_allowed = 1;
     let num_duplication_allowed = 1;
-    let goal = if config.reliable && config.once {
-        goals::all_goals(vec![
-            goals::got_n_local_messages("receiver-node", "receiver", msg_count),
-            goals::no_events(),
-        ])
-    } else {
-        goals::no_events()
-    };
-    let mut invariants = vec![
-        invariants::state_depth(20),
-        mc_invariant_guarantees(messages.clone(), *config),
-    ];
-    if config.ordered {
-        invariants.push(invariants::time_limit(Duration::from_secs(80)))
+    let goal = || {
+        if config.reliable && config.once {
+            // every message must be delivered exactly once, not just the total number of messages
+            let mut delivered: Vec<GoalFn> = messages
+                .iter()
+                .map(|msg| goals::delivered_exactly("receiver-node", "receiver", msg.data.clone(), 1))
+                .collect();
+            delivered.push(goals::no_events());
+            goals::all_goals(delivered)
+        } else {
+            goals::no_events()
+        }
     };
-    let strategy_config = StrategyConfig::default()
-        .prune(prunes::any_prune(vec![
+    let prune = || {
+        let prune = prunes::any_prune(vec![
             prunes::events_limit(LogEntry::is_mc_message_dropped, num_drops_allowed),
             prunes::events_limit(LogEntry::is_mc_message_duplicated, num_duplication_allowed),
             prunes::events_limit(LogEntry::is_mc_timer_fired, 1),
             prunes::events_limit(LogEntry::is_mc_message_received, msg_count + num_drops_allowed),
-        ]))
-        .goal(goal)
-        .invariant(invariants::all_invariants(invariants));
-    let mut mc = ModelChecker::new(&sys);
-
-    let res = mc.run_with_change::<Bfs>(strategy_config, |sys| {
-        for msg in messages {
-            sys.send_local_message("sender-node", "sender", msg.clone());
-        }
-    });
-    if let Err(e) = res {
-        e.print_trace();
-        Err(e.message())
-    } else {
-        Ok(true)
+        ]);
+        reduced(config, &messages, prune)
+    };
+    let invariant = || {
+        let mut invariants = vec![
+            invariants::state_depth(20),
+            check_invariant_every(
+                config.mc_check_every,
+                mc_invariant_guarantees(messages.clone(), *config),
+                goals::any_goal(vec![goal(), prune()]),
+            ),
+        ];
+        if config.ordered {
+            invariants.push(invariants::time_limit(Duration::from_secs(80)))
+        };
+        invariants::all_invariants(invariants)
+    };
+    let wall_clock = WallClockLimit::new(config.mc_time_limit);
+    let res = run_search(
+        config,
+        &sys,
+        || wall_clock.prune(prune()),
+        goal,
+        invariant,
+        undelivered_messages(messages.len()),
+        send_mc_messages(&messages),
+    );
+    wall_clock.result(res)
+}
+
+pub fn test_mc_scenario(config: &TestConfig) -> TestResult {
+    let scenario = config.mc_scenario.unwrap();
+    let mut sys = build_system(config, false);
+    sys.network().set_drop_rate(scenario.drop_rate);
+    sys.network().set_dupl_rate(scenario.dupl_rate);
+    let messages = mc_messages(&mut sys, scenario.message_count);
+    let prune = || reduced(config, &messages, scenario.prune());
+    let invariant = || {
+        let mut invariants = scenario.invariants();
+        invariants.push(check_invariant_every(
+            config.mc_check_every,
+            mc_invariant_guarantees(messages.clone(), *config),
+            goals::any_goal(vec![scenario.goal(), prune()]),
+        ));
+        invariants::all_invariants(invariants)
+    };
+    let wall_clock = WallClockLimit::new(config.mc_time_limit);
+    let res = run_search(
+        config,
+        &sys,
+        || wall_clock.prune(prune()),
+        || scenario.goal(),
+        invariant,
+        undelivered_messages(messages.len()),
+        send_mc_messages(&messages),
+    );
+    wall_clock.result(res)
+}
+
+pub fn test_mc_from_snapshot(config: &TestConfig) -> TestResult {
+    let mut sys = build_system(config, false);
+    let messages = mc_messages(&mut sys, 2);
+    for message in &messages {
+        sys.send_local_message("sender", message.clone());
+    }
+    // the snapshot is checked twice while the messages are in flight: once with the network kept reliable
+    // and once with the following messages possibly duplicated
+    let snapshot = SystemSnapshot::take(&sys);
+    let networks: [(&str, f64); 2] = [("reliable", 0.), ("duplicating", 0.5)];
+    for (network, dupl_rate) in networks {
+        let goal = || {
+            if config.reliable && config.once {
+                goals::all_goals(vec![
+                    goals::got_n_local_messages("receiver-node", "receiver", messages.len()),
+                    goals::no_events(),
+                ])
+            } else {
+                goals::no_events()
+            }
+        };
+        let prune = prunes::any_prune(vec![
+            prunes::events_limit(LogEntry::is_mc_message_duplicated, 1),
+            prunes::events_limit(LogEntry::is_mc_timer_fired, 1),
+        ]);
+        let strategy_config = StrategyConfig::default()
+            .prune(reduced(config, &messages, prune))
+            .goal(goal())
+            .invariant(invariants::all_invariants(vec![
+                invariants::state_depth(20),
+                mc_invariant_guarantees(messages.clone(), *config),
+            ]));
+        let res = snapshot.model_check::<Bfs>(&sys, strategy_config, |sys| sys.network().set_dupl_rate(dupl_rate));
+        if let Err(e) = res {
+            e.print_trace();
+            return Err(format!("{network} network: {}", e.message()));
+        }
     }
+    Ok(true)
 }